@@ -42,33 +42,27 @@ impl AsyncCommand {
                     };
                     self.eof = true;
                 }
-                _ => panic!(err),
+                _ => panic!("{}", err),
             },
         };
         Some(output)
     }
 
     pub async fn update_and_get_log(&mut self) -> (&str, bool) {
-        match self.get_outputted_content().await {
-            Some(value) => {
-                for char in value {
-                    self.undecoded.push(char);
-                    if self.undecoded.len() > 4 {
-                        self.output.push(REPLACEMENT_CHARACTER);
+        if let Some(value) = self.get_outputted_content().await {
+            for char in value {
+                self.undecoded.push(char);
+                if self.undecoded.len() > 4 {
+                    self.output.push(REPLACEMENT_CHARACTER);
+                    self.undecoded.clear();
+                } else {
+                    let tried_into_string = String::from_utf8(self.undecoded.clone());
+                    if let Ok(value) = tried_into_string {
+                        self.output.push_str(&value);
                         self.undecoded.clear();
-                    } else {
-                        let tried_into_string = String::from_utf8(self.undecoded.clone());
-                        match tried_into_string {
-                            Ok(value) => {
-                                self.output.push_str(&value);
-                                self.undecoded.clear();
-                            }
-                            Err(_) => (),
-                        };
                     };
-                }
+                };
             }
-            None => (),
         };
         (&self.output, self.eof)
     }
@@ -1,12 +1,166 @@
-use crate::input::{FixedInput, UpdatableInput};
-use std::collections::BTreeMap;
+use crate::input::{FetchInputError, FixedInput, LockState, UpdatableInput};
+use crate::lock_file::{LoadLockError, LockFile};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use async_std::fs::File;
 use async_std::io::prelude::WriteExt;
 
+/// the current time, in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// format a time in seconds since the unix epoch as an UTC date, like `2021-01-31`
+pub fn format_date(timestamp: u64) -> String {
+    // convert the number of days since the epoch to a civil date (from Howard Hinnant's
+    // `civil_from_days` algorithm)
+    let days = (timestamp / (24 * 60 * 60)) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// parse a duration like `7d`, `12h` or `1w2d`, and return it in seconds. Accepted units are
+/// `s`, `m`, `h`, `d` and `w`. Durations that don't fit in an u64 are rejected.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for char in text.trim().chars() {
+        if char.is_ascii_digit() {
+            number.push(char);
+            continue;
+        };
+        let unit = match char {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            'w' => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return None;
+    };
+    Some(total)
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "invalid duration {:?} (expected something like \"7d\" or \"12h\")",
+            text
+        ))
+    })
+}
+
+/// When a locked input should be resolved again
+#[derive(Deserialize, Hash, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdatePolicy {
+    /// keep the locked version until it is explicitly updated
+    #[default]
+    Never,
+    /// resolve it again once the lock is older than the given number of seconds (written as a
+    /// duration like `"7d"` in the configuration)
+    After(#[serde(deserialize_with = "deserialize_duration")] u64),
+    /// resolve it again on every apply
+    Always,
+}
+
+impl UpdatePolicy {
+    /// return the policy that update the most often of the two
+    pub fn most_eager(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Always, _) | (_, Self::Always) => Self::Always,
+            (Self::After(a), Self::After(b)) => Self::After(a.min(b)),
+            (Self::After(a), Self::Never) | (Self::Never, Self::After(a)) => Self::After(a),
+            (Self::Never, Self::Never) => Self::Never,
+        }
+    }
+
+    /// check if an input fetched at `fetched_at` (None if unknown) should be resolved again
+    pub fn is_expired(self, fetched_at: Option<u64>, now: u64) -> bool {
+        match self {
+            Self::Never => false,
+            Self::After(duration) => fetched_at
+                .map(|fetched_at| fetched_at.saturating_add(duration) <= now)
+                .unwrap_or(true),
+            Self::Always => true,
+        }
+    }
+}
+
+/// A locked input, with the time it was resolved at
+#[derive(Serialize, Deserialize, Hash, Clone, Debug)]
+pub struct LockedInput {
+    pub fixed: FixedInput,
+    /// when this input was resolved, in seconds since the unix epoch. None for input coming from
+    /// lock file that didn't record it.
+    pub fetched_at: Option<u64>,
+    /// since when this input isn't used by any enabled config source, if it isn't
+    #[serde(default = "Option::default")]
+    pub unused_since: Option<u64>,
+}
+
+/// The inputs removed from the lock by [`CachedFixedInput::prune`], and the unused inputs it kept
+#[derive(Default, Clone, Debug)]
+pub struct PruneReport {
+    pub pruned: Vec<(UpdatableInput, FixedInput)>,
+    /// the unused inputs still in the lock, with when they will be removed
+    pub kept: Vec<(UpdatableInput, u64)>,
+}
+
+impl PruneReport {
+    pub fn is_empty(&self) -> bool {
+        self.pruned.is_empty() && self.kept.is_empty()
+    }
+
+    /// A human readable list of the pruned and kept inputs
+    pub fn describe(&self) -> String {
+        let mut result = String::new();
+        if !self.pruned.is_empty() {
+            result.push_str("pruned the unused pins :\n");
+            for (input, _) in &self.pruned {
+                result.push_str(&format!("    {}\n", input.describe()));
+            }
+        };
+        if !self.kept.is_empty() {
+            result.push_str("kept the unused pins :\n");
+            for (input, until) in &self.kept {
+                result.push_str(&format!(
+                    "    {} (kept until {})\n",
+                    input.describe(),
+                    format_date(*until)
+                ));
+            }
+        };
+        result
+    }
+}
+
 #[derive(Hash, Default, Clone, Debug)]
 pub struct CachedFixedInput {
-    cache: BTreeMap<UpdatableInput, FixedInput>,
+    cache: BTreeMap<UpdatableInput, LockedInput>,
 }
 
 impl CachedFixedInput {
@@ -14,50 +168,188 @@ impl CachedFixedInput {
         Self::default()
     }
 
+    /// insert a newly resolved input, marking it as fetched now
     pub fn insert(&mut self, k: UpdatableInput, v: FixedInput) -> Option<FixedInput> {
-        self.cache.insert(k, v)
+        self.cache
+            .insert(
+                k,
+                LockedInput {
+                    fixed: v,
+                    fetched_at: Some(now()),
+                    unused_since: None,
+                },
+            )
+            .map(|previous| previous.fixed)
     }
 
     pub fn get(&self, k: &UpdatableInput) -> Option<&FixedInput> {
+        self.cache.get(k).map(|locked| &locked.fixed)
+    }
+
+    pub fn get_locked(&self, k: &UpdatableInput) -> Option<&LockedInput> {
         self.cache.get(k)
     }
 
     pub fn remove(&mut self, k: &UpdatableInput) -> Option<FixedInput> {
-        self.cache.remove(k)
+        self.cache.remove(k).map(|locked| locked.fixed)
     }
 
-    pub async fn get_or_insert_latest(&mut self, k: &UpdatableInput) -> FixedInput {
-        if let Some(get) = self.get(k) {
-            get.clone()
-        } else {
-            let latest = k.get_latest().await;
-            self.insert(k.clone(), latest.clone());
-            self.get(k).unwrap().clone()
+    pub fn iter(&self) -> impl Iterator<Item = (&UpdatableInput, &FixedInput)> {
+        self.cache.iter().map(|(k, locked)| (k, &locked.fixed))
+    }
+
+    /// remove the inputs that aren't in `used` since at least `grace_period` seconds. The other
+    /// unused inputs are marked as such, so they will be removed once the grace period is over.
+    pub fn prune(
+        &mut self,
+        used: &BTreeSet<UpdatableInput>,
+        grace_period: u64,
+        now: u64,
+    ) -> PruneReport {
+        let mut report = PruneReport::default();
+        for (k, locked) in self.cache.iter_mut() {
+            if used.contains(k) {
+                locked.unused_since = None;
+            } else {
+                let unused_since = *locked.unused_since.get_or_insert(now);
+                let until = unused_since.saturating_add(grace_period);
+                if until <= now {
+                    report.pruned.push((k.clone(), locked.fixed.clone()));
+                } else {
+                    report.kept.push((k.clone(), until));
+                };
+            };
         }
+        for (k, _) in &report.pruned {
+            self.cache.remove(k);
+        }
+        report
+    }
+
+    /// put back the entries of `previous` accepted by `can_restore` in this lock, replacing the
+    /// current one if needed
+    pub fn restore_from<F: Fn(&UpdatableInput) -> bool>(
+        &mut self,
+        previous: &CachedFixedInput,
+        can_restore: F,
+    ) {
+        for (k, locked) in &previous.cache {
+            if can_restore(k) {
+                self.cache.insert(k.clone(), locked.clone());
+            }
+        }
+    }
+
+    /// return the locked version of this input, locking it to its latest version if it isn't
+    /// already. Also return the state the input was in before, or None if it wasn't locked.
+    /// Stale input (local path that changed since they were locked) and input whose lock expired
+    /// according to `policy` are locked again.
+    pub async fn get_or_insert_latest(
+        &mut self,
+        k: &UpdatableInput,
+        policy: UpdatePolicy,
+    ) -> Result<(FixedInput, Option<LockState>), FetchInputError> {
+        let previous_state = if let Some(get) = self.get_locked(k) {
+            let state = if policy.is_expired(get.fetched_at, now()) {
+                LockState::Expired
+            } else {
+                get.fixed.lock_state().await?
+            };
+            if state == LockState::UpToDate {
+                return Ok((get.fixed.clone(), Some(state)));
+            };
+            Some(state)
+        } else {
+            None
+        };
+        let latest = k.get_latest().await?;
+        self.insert(k.clone(), latest.clone());
+        Ok((latest, previous_state))
     }
 
     pub async fn write_lock(&self, lock_file: &std::path::Path) {
         let mut file = File::create(lock_file).await.unwrap();
-        let to_serialize: Vec<(UpdatableInput, FixedInput)> =
-            self.cache.iter().fold(Vec::new(), |mut vec, (k, v)| {
-                vec.push((k.clone(), v.clone()));
-                vec
-            });
+        let to_serialize = LockFile::new(self.cache.iter().fold(Vec::new(), |mut vec, (k, v)| {
+            vec.push((k.clone(), v.clone()));
+            vec
+        }));
         let to_write = serde_json::to_vec_pretty(&to_serialize).unwrap();
         file.write_all(&to_write).await.unwrap();
     }
 
-    pub fn new_from_lock(lock_file: &std::path::Path) -> Self {
-        let file = std::fs::File::open(lock_file).unwrap();
-        let mut deserialized: Vec<(UpdatableInput, FixedInput)> =
-            serde_json::from_reader(&file).unwrap();
-        Self {
+    /// load the lock file, migrating it if it was written by an older version of nixminigui
+    pub fn new_from_lock(lock_file: &std::path::Path) -> Result<Self, LoadLockError> {
+        let mut deserialized = LockFile::read(lock_file)?;
+        Ok(Self {
             cache: deserialized
+                .inputs
                 .drain(..)
                 .fold(BTreeMap::new(), |mut map, (k, v)| {
                     map.insert(k, v);
                     map
                 }),
-        }
+        })
     }
 }
+
+#[test]
+fn test_prune() {
+    let input = |package: &str| UpdatableInput::SystemWide {
+        package: package.to_string(),
+        floating: true,
+    };
+    let mut cache = CachedFixedInput::new();
+    for package in &["used", "unused"] {
+        cache.insert(input(package), FixedInput::SystemWide(package.to_string()));
+    }
+    let used: BTreeSet<UpdatableInput> = vec![input("used")].into_iter().collect();
+    let report = cache.prune(&used, 100, 1000);
+    assert!(report.pruned.is_empty());
+    assert_eq!(report.kept, vec![(input("unused"), 1100)]);
+    assert_eq!(
+        cache.get_locked(&input("unused")).unwrap().unused_since,
+        Some(1000)
+    );
+    assert!(cache.prune(&used, 100, 1099).pruned.is_empty());
+    let report = cache.prune(&used, 100, 1100);
+    assert_eq!(report.pruned.len(), 1);
+    assert_eq!(report.pruned[0].0, input("unused"));
+    assert!(report.kept.is_empty());
+    assert!(cache.get(&input("unused")).is_none());
+    assert!(cache.get(&input("used")).is_some());
+    cache.insert(input("removed at once"), FixedInput::SystemWide("x".into()));
+    assert_eq!(cache.prune(&used, 0, 2000).pruned.len(), 1);
+    cache.insert(input("unused"), FixedInput::SystemWide("x".into()));
+    let report = cache.prune(&used, u64::MAX, 2000);
+    assert_eq!(report.kept, vec![(input("unused"), u64::MAX)]);
+
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(951_782_400), "2000-02-29");
+    assert_eq!(format_date(1_612_137_599), "2021-01-31");
+}
+
+#[test]
+fn test_update_policy() {
+    assert_eq!(parse_duration("7d"), Some(7 * 24 * 60 * 60));
+    assert_eq!(parse_duration("1w12h"), Some((7 * 24 + 12) * 60 * 60));
+    assert_eq!(parse_duration("12"), None);
+    assert_eq!(parse_duration("3y"), None);
+    assert_eq!(parse_duration("99999999999999999d"), None);
+    assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    let policy: UpdatePolicy = serde_json::from_str(r#"{"after": "1h"}"#).unwrap();
+    assert_eq!(policy, UpdatePolicy::After(3600));
+    assert!(!policy.is_expired(Some(1000), 1000 + 3599));
+    assert!(policy.is_expired(Some(1000), 1000 + 3600));
+    assert!(policy.is_expired(None, 0));
+    assert!(!UpdatePolicy::Never.is_expired(None, 0));
+    assert!(UpdatePolicy::Always.is_expired(Some(1000), 1000));
+    assert_eq!(
+        policy.most_eager(UpdatePolicy::After(60)),
+        UpdatePolicy::After(60)
+    );
+    assert_eq!(policy.most_eager(UpdatePolicy::Never), policy);
+    assert_eq!(
+        serde_json::from_str::<UpdatePolicy>(r#""always""#).unwrap(),
+        UpdatePolicy::Always
+    );
+}
@@ -1,19 +1,39 @@
-use crate::cached_fixed_input::CachedFixedInput;
-use crate::config_source::{ConfigSource, LoadConfigError};
+use crate::cached_fixed_input::{now, CachedFixedInput, PruneReport, UpdatePolicy};
+use crate::config_source::{ConfigSource, LoadConfigError, UserConfigError};
+use crate::config_value::ConfigValue;
+use crate::gate::SourcesState;
 
-use crate::input::UpdatableInput;
+use crate::input::{make_absolute, FetchInputError, FixedInput, LockState, UpdatableInput};
+use crate::input_graph::InputGraph;
+use crate::input_override::{apply_overrides, InputOverride};
 
-use crate::inputs_set::InputsSet;
+use crate::inputs_set::{InputsSet, InputsSetError, LinkToName};
+use crate::lock_file::LoadLockError;
 
 use crate::nixtool::escape_string;
 use crate::nixtool::generate_dict_from_btreemap;
 use crate::nixtool::to_nix_vec;
 use crate::ongoing_save::OngoingSave;
-use crate::saved_config::SavedConfig;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use crate::saved_config::{SaveConfigError, SavedConfig, SavedSecrets};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 
-pub type UserConfiguration = BTreeMap<String, String>;
+pub type UserConfiguration = BTreeMap<String, ConfigValue>;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum GeneratePackageError {
+        CantWriteFile { path: PathBuf, err: std::io::Error } {
+            cause(err)
+            display(me) -> ("can't write the file {:?}: {}", path, err)
+        }
+        UserConfig(err: UserConfigError) {
+            from()
+            cause(err)
+            display(me) -> ("{}", err)
+        }
+    }
+}
 
 #[derive(Clone, Hash)]
 pub struct ConfigManager {
@@ -23,10 +43,17 @@ pub struct ConfigManager {
     lock_file: PathBuf,
     package_nix_path: PathBuf,
     cached_fixed_input: CachedFixedInput,
+    /// how many days the pins of inputs no longer used are kept in the lock file
+    keep_unused_pins_days: u64,
+    /// the inputs that will be resolved again on the next apply, even if they are already locked
+    pending_updates: BTreeSet<UpdatableInput>,
+    /// the rules that replace an input by another in every config source
+    input_overrides: Vec<InputOverride>,
+    /// the secrets read from the secrets file. They are moved to the configuration of their config
+    /// source once it is loaded.
+    saved_secrets: SavedSecrets,
 }
 
-//TODO: timed cache for updatable_input (key: the updateinput, out: the fixedinput)
-
 impl ConfigManager {
     pub fn new(user_config_path: PathBuf, lock_file: PathBuf, package_nix_path: PathBuf) -> Self {
         Self {
@@ -36,9 +63,38 @@ impl ConfigManager {
             lock_file,
             package_nix_path,
             cached_fixed_input: CachedFixedInput::new(),
+            keep_unused_pins_days: 0,
+            pending_updates: BTreeSet::new(),
+            input_overrides: Vec::new(),
+            saved_secrets: SavedSecrets::default(),
         }
     }
 
+    /// the file the secret options are saved in, next to the user configuration
+    pub fn secrets_path(&self) -> PathBuf {
+        let path = self.user_config_path.with_extension("secrets.json");
+        if path.is_relative() {
+            if let Ok(current_dir) = std::env::current_dir() {
+                return current_dir.join(path);
+            };
+        };
+        path
+    }
+
+    /// add the saved secrets to the configuration of a loaded config source, and convert its
+    /// values to their current type
+    fn load_values(&mut self, uid: usize) {
+        let entry = &mut self.configs[uid];
+        if let Some(source) = &entry.0 {
+            if let Some(secrets) = self.saved_secrets.secrets.remove(&source.entry.id) {
+                for (id, secret) in secrets {
+                    entry.2.insert(id, ConfigValue::String(secret));
+                }
+            };
+            source.entry.migrate_values(&mut entry.2);
+        };
+    }
+
     /// add a configuration source. Will replace the previous one if it has the same id.
     pub fn add_configuration_source(&mut self, config_source: ConfigSource) {
         let always_enabled = config_source.entry.always_enabled;
@@ -54,14 +110,7 @@ impl ConfigManager {
                 .push((Some(config_source), always_enabled, BTreeMap::new()));
             position
         };
-        let entry = &mut self.configs[key];
-        for configuration in &entry.0.as_ref().unwrap().entry.configurations {
-            if !entry.2.contains_key(&configuration.id) {
-                if let Some(value) = configuration.kind.default_value() {
-                    entry.2.insert(configuration.id.clone(), value);
-                }
-            }
-        }
+        self.load_values(key);
     }
 
     /// load a configuration source from a path. Will replace the previous one if it has the same
@@ -79,7 +128,7 @@ impl ConfigManager {
         if let Some(conf_id) = self.key_to_id.get(key) {
             if let Some(config_source) = &self.configs[*conf_id].0 {
                 Some((
-                    &config_source,
+                    config_source,
                     self.configs[*conf_id].1,
                     &self.configs[*conf_id].2,
                 ))
@@ -95,13 +144,7 @@ impl ConfigManager {
         self.configs
             .iter()
             .filter(|x| !x.1)
-            .filter_map(|x| {
-                if let Some(config_source) = &x.0 {
-                    Some((config_source, x.1, &x.2))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|x| x.0.as_ref().map(|config_source| (config_source, x.1, &x.2)))
             .collect()
     }
 
@@ -109,13 +152,7 @@ impl ConfigManager {
         self.configs
             .iter()
             .filter(|x| x.1)
-            .filter_map(|x| {
-                if let Some(config_source) = &x.0 {
-                    Some((config_source, x.1, &x.2))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|x| x.0.as_ref().map(|config_source| (config_source, x.1, &x.2)))
             .collect()
     }
 
@@ -126,8 +163,23 @@ impl ConfigManager {
         &mut self.configs[*self.key_to_id.get(key).unwrap()]
     }
 
+    /// enable a config source, and the config sources whose exported inputs it use
     pub fn enable_config(&mut self, key: &str) {
-        self.get_config_mut(key).1 = true;
+        let config = self.get_config_mut(key);
+        if config.1 {
+            return;
+        };
+        config.1 = true;
+        let required = config
+            .0
+            .as_ref()
+            .map(|config_source| config_source.entry.required_config_sources())
+            .unwrap_or_default();
+        for required_key in required {
+            if self.key_to_id.contains_key(&required_key) {
+                self.enable_config(&required_key);
+            }
+        }
     }
 
     pub fn disable_config(&mut self, key: &str) {
@@ -145,45 +197,82 @@ impl ConfigManager {
         OngoingSave::new(self.clone())
     }
 
-    pub fn set_configuration(&mut self, key: String, id: String, value: String) {
+    /// continue the save and apply once the update of the lock has been reviewed
+    pub fn save_and_apply_with_reviewed_lock(&self) -> OngoingSave {
+        OngoingSave::new_with_reviewed_lock(self.clone())
+    }
+
+    pub fn set_configuration(&mut self, key: String, id: String, value: ConfigValue) {
         self.get_config_mut(&key).2.insert(id, value);
     }
 
-    pub async fn save_to_config_file(&self) {
-        let mut saved_config = SavedConfig::default();
+    pub async fn save_to_config_file(&self) -> Result<(), SaveConfigError> {
+        let mut saved_config = SavedConfig {
+            keep_unused_pins_days: self.keep_unused_pins_days,
+            input_overrides: self.input_overrides.clone(),
+            ..SavedConfig::default()
+        };
+        let mut saved_secrets = self.saved_secrets.clone();
         for (key, uid) in self.key_to_id.iter() {
-            saved_config.configurations.insert(
-                key.to_string(),
-                (self.configs[*uid].1, self.configs[*uid].2.clone()),
-            );
+            let (source, enabled, status) = &self.configs[*uid];
+            let values = match source {
+                Some(source) => {
+                    let secrets = source.entry.secrets_to_save(status);
+                    if !secrets.is_empty() {
+                        saved_secrets.secrets.insert(key.to_string(), secrets);
+                    };
+                    source.entry.values_to_save(status)
+                }
+                None => status.clone(),
+            };
+            saved_config
+                .configurations
+                .insert(key.to_string(), (*enabled, values));
         }
+        saved_secrets.save(&self.secrets_path()).await?;
         let save_content = serde_json::to_vec(&saved_config).unwrap();
         use async_std::fs::File;
         use async_std::prelude::*;
-        let mut file = File::create(&self.user_config_path).await.unwrap();
-        file.write_all(&save_content).await.unwrap();
+        let write = async {
+            let mut file = File::create(&self.user_config_path).await?;
+            file.write_all(&save_content).await
+        };
+        write.await.map_err(|err| SaveConfigError::CantWriteFile {
+            path: self.user_config_path.clone(),
+            err,
+        })
     }
 
+    /// write the package file, and return its path. What the user should know about is added to
+    /// `warnings`.
     pub async fn write_nix_package_file(
         &self,
         input_set: &InputsSet,
         link_to_name: &BTreeMap<String, BTreeMap<String, String>>,
-    ) -> PathBuf {
-        let package_file = self
-            .generate_nix_package_file(input_set, link_to_name)
-            .await;
+        warnings: &mut Vec<String>,
+    ) -> Result<PathBuf, GeneratePackageError> {
+        let package_file = self.generate_nix_package_file(input_set, link_to_name, warnings)?;
         use async_std::fs::File;
         use async_std::prelude::*;
-        let mut file = File::create(&self.package_nix_path).await.unwrap();
-        file.write_all(package_file.as_bytes()).await.unwrap();
-        self.package_nix_path.clone()
+        let write = async {
+            let mut file = File::create(&self.package_nix_path).await?;
+            file.write_all(package_file.as_bytes()).await
+        };
+        write
+            .await
+            .map_err(|err| GeneratePackageError::CantWriteFile {
+                path: self.package_nix_path.clone(),
+                err,
+            })?;
+        Ok(self.package_nix_path.clone())
     }
 
-    pub async fn generate_nix_package_file(
+    pub fn generate_nix_package_file(
         &self,
         input_set: &InputsSet,
         link_to_name: &BTreeMap<String, BTreeMap<String, String>>,
-    ) -> String {
+        warnings: &mut Vec<String>,
+    ) -> Result<String, GeneratePackageError> {
         let mut packages_string: Vec<String> = Vec::new();
         for dependancy in self.enabled_entry().iter() {
             if let Some(package) = &dependancy.0.entry.effects.package {
@@ -195,24 +284,24 @@ impl ConfigManager {
                     map
                 });
 
+                let user_config = dependancy.0.entry.user_config_nix(
+                    dependancy.2,
+                    self,
+                    &self.secrets_path(),
+                    warnings,
+                )?;
                 package_inputs.insert(
                     "user_config".into(),
-                    generate_dict_from_btreemap(&dependancy.2.iter().fold(
-                        BTreeMap::new(),
-                        |mut map, (k, v)| {
-                            map.insert(escape_string(k), escape_string(v));
-                            map
-                        },
-                    )),
+                    generate_dict_from_btreemap(&user_config),
                 );
-                let mut package_distant = UpdatableInput::LocalPath {
-                    path: PathBuf::from(package.path.clone()),
-                    is_absolute: false,
-                };
-                package_distant.ensure_path_is_absolute(&dependancy.0.folder_root);
+                // the package is imported from the folder of its config source rather than copied
+                // alone to the store, so it can import the other files of this folder
+                let package_path =
+                    make_absolute(&dependancy.0.folder_root, Path::new(&package.path));
                 let package_expression = format!(
                     "(import {} {})",
-                    package_distant.get_latest().await.generate_nix_fetch(),
+                    FixedInput::LocalPath(package_path.to_string_lossy().to_string())
+                        .generate_nix_fetch(),
                     generate_dict_from_btreemap(&package_inputs)
                 );
                 packages_string.push(package_expression);
@@ -228,49 +317,84 @@ impl ConfigManager {
                         map.insert(k.to_string(), input_set.get_name(*v));
                         map
                     });
-            inputs_list.insert(
-                input_set.get_name(count),
-                format!(
-                    "import {} {}",
-                    self.cached_fixed_input
-                        .get(&dependancy.distant)
-                        .unwrap()
-                        .generate_nix_fetch(),
-                    generate_dict_from_btreemap(&deps_of_dep)
-                ),
-            );
+            let fixed = self.cached_fixed_input.get(&dependancy.distant).unwrap();
+            let input_expression = if fixed.is_value() {
+                fixed.generate_nix_fetch()
+            } else {
+                dependancy
+                    .import
+                    .generate_nix(&fixed.generate_nix_fetch(), &deps_of_dep)
+            };
+            inputs_list.insert(input_set.get_name(count), input_expression);
         }
-        format!(
+        Ok(format!(
             "{{}}:\nlet\ninputs = rec {};\nin\n{}",
             generate_dict_from_btreemap(&inputs_list),
             to_nix_vec(&packages_string)
-        )
+        ))
     }
 
-    pub async fn generate_inputs_set_for_enabled(
+    pub fn generate_inputs_set_for_enabled(
         &self,
-    ) -> (InputsSet, BTreeMap<String, BTreeMap<String, String>>) {
+    ) -> Result<(InputsSet, LinkToName), InputsSetError> {
         let mut inputs_set = InputsSet::new();
-        let mut inputs = BTreeMap::new();
+        let mut groups = BTreeMap::new();
         for dependancy in self.enabled_entry().iter() {
-            inputs.insert(
-                dependancy.0.entry.id.to_string(),
-                inputs_set.add_group(dependancy.0.entry.effects.inputs.clone()),
-            );
+            let mut group = dependancy.0.entry.effects.inputs.clone();
+            for declaration in group.values_mut() {
+                declaration.distant = self.effective_input(&declaration.distant).clone();
+            }
+            groups.insert(dependancy.0.entry.id.to_string(), group);
         }
-        (inputs_set, inputs)
+        let inputs = inputs_set.add_groups(groups)?;
+        Ok((inputs_set, inputs))
     }
 
-    pub async fn ensure_fixed_is_loaded(&mut self, input: &UpdatableInput) {
-        self.cached_fixed_input.get_or_insert_latest(input).await;
+    /// the graph of the inputs of the enabled config sources, as they would be generated now
+    pub fn input_graph(&self) -> Result<InputGraph, InputsSetError> {
+        let (inputs_set, link_to_name) = self.generate_inputs_set_for_enabled()?;
+        Ok(InputGraph::new(
+            &inputs_set,
+            &link_to_name,
+            &self.cached_fixed_input,
+        ))
+    }
+
+    /// ensure the input is locked (and that the lock isn't expired according to `policy`), and
+    /// return the state it was in before (None if it wasn't locked)
+    pub async fn ensure_fixed_is_loaded(
+        &mut self,
+        input: &UpdatableInput,
+        policy: UpdatePolicy,
+    ) -> Result<Option<LockState>, FetchInputError> {
+        let policy = if self.pending_updates.contains(input) {
+            UpdatePolicy::Always
+        } else {
+            policy
+        };
+        Ok(self
+            .cached_fixed_input
+            .get_or_insert_latest(input, policy)
+            .await?
+            .1)
     }
 
     pub fn load_config(&mut self) {
         let user_configs = SavedConfig::new_from_path(&self.user_config_path);
+        self.saved_secrets = SavedSecrets::new_from_path(&self.secrets_path());
+        self.keep_unused_pins_days = user_configs.keep_unused_pins_days;
+        // the saved rules are already in their order of precedence
+        self.input_overrides = user_configs.input_overrides.clone();
+        if let Some(base_dir) = self.user_config_path.parent() {
+            for input_override in self.input_overrides.iter_mut() {
+                input_override.ensure_path_is_absolute(base_dir);
+            }
+        };
         for (key, (enabled, config)) in user_configs.configurations.iter() {
-            if let Some(uid) = self.key_to_id.get(key) {
-                self.configs[*uid].1 = *enabled;
-                self.configs[*uid].2 = config.clone();
+            if let Some(uid) = self.key_to_id.get(key).copied() {
+                self.configs[uid].1 = *enabled;
+                self.configs[uid].2 = config.clone();
+                self.load_values(uid);
             } else {
                 let uid = self.configs.len();
                 self.key_to_id.insert(key.clone(), uid);
@@ -279,11 +403,278 @@ impl ConfigManager {
         }
     }
 
+    pub fn keep_unused_pins_days(&self) -> u64 {
+        self.keep_unused_pins_days
+    }
+
+    pub fn set_keep_unused_pins_days(&mut self, days: u64) {
+        self.keep_unused_pins_days = days;
+    }
+
+    pub fn input_overrides(&self) -> &[InputOverride] {
+        &self.input_overrides
+    }
+
+    /// add an override rule. It take precedence over the existing rules for the same input.
+    pub fn add_input_override(&mut self, mut input_override: InputOverride) {
+        if let Some(base_dir) = self.user_config_path.parent() {
+            input_override.ensure_path_is_absolute(base_dir);
+        };
+        self.input_overrides
+            .retain(|rule| rule.from != input_override.from);
+        self.input_overrides.insert(0, input_override);
+    }
+
+    pub fn remove_input_override(&mut self, from: &UpdatableInput) {
+        self.input_overrides.retain(|rule| &rule.from != from);
+    }
+
+    /// the input that is used in place of `input`, once the override rules are applied
+    pub fn effective_input<'a>(&'a self, input: &'a UpdatableInput) -> &'a UpdatableInput {
+        apply_overrides(&self.input_overrides, input)
+    }
+
+    /// remove from the lock the inputs that aren't used by `inputs_set` since more than the
+    /// configured grace period, and report them with the unused inputs that are kept.
+    pub fn prune_lock(&mut self, inputs_set: &InputsSet) -> PruneReport {
+        let used = inputs_set
+            .dependancies
+            .iter()
+            .map(|input| input.distant.clone())
+            .collect();
+        self.cached_fixed_input.prune(
+            &used,
+            self.keep_unused_pins_days.saturating_mul(24 * 60 * 60),
+            now(),
+        )
+    }
+
+    /// update every input of every enabled config source on the next apply
+    pub fn update_all_inputs(&mut self) {
+        for inputs in self.inputs_by_config_source().into_values() {
+            self.pending_updates.extend(inputs.into_values());
+        }
+    }
+
+    /// update every input of the given config source on the next apply. Other config sources
+    /// using the same inputs will also see the update.
+    pub fn update_inputs_of_config(&mut self, key: &str) {
+        if let Some((config_source, _, _)) = self.get_config(key) {
+            let inputs: Vec<UpdatableInput> = config_source
+                .entry
+                .effects
+                .inputs
+                .values()
+                .map(|declaration| self.effective_input(&declaration.distant).clone())
+                .collect();
+            self.pending_updates.extend(inputs);
+        }
+    }
+
+    /// update the input named `name` of the given config source on the next apply
+    pub fn update_input(&mut self, key: &str, name: &str) {
+        if let Some(input) = self.input_of_config(key, name) {
+            self.pending_updates.insert(input);
+        }
+    }
+
+    /// the input named `name` of the given config source, once the override rules are applied
+    pub fn input_of_config(&self, key: &str, name: &str) -> Option<UpdatableInput> {
+        self.get_config(key)
+            .and_then(|(config_source, _, _)| config_source.entry.effects.inputs.get(name))
+            .map(|declaration| self.effective_input(&declaration.distant).clone())
+    }
+
+    /// true if the input will be updated on the next apply
+    pub fn is_update_pending(&self, input: &UpdatableInput) -> bool {
+        self.pending_updates.contains(input)
+    }
+
+    /// forget the requested updates, once they have been applied
+    pub fn clear_pending_updates(&mut self) {
+        self.pending_updates.clear();
+    }
+
+    pub fn cached_fixed_input(&self) -> &CachedFixedInput {
+        &self.cached_fixed_input
+    }
+
+    /// replace the lock (in memory only, see `write_lock`)
+    pub fn set_cached_fixed_input(&mut self, cached_fixed_input: CachedFixedInput) {
+        self.cached_fixed_input = cached_fixed_input;
+    }
+
+    /// return the inputs of each enabled config source, by their name, once the override rules
+    /// are applied
+    pub fn inputs_by_config_source(&self) -> BTreeMap<String, BTreeMap<String, UpdatableInput>> {
+        self.enabled_entry()
+            .iter()
+            .map(|(config_source, _, _)| {
+                (
+                    config_source.entry.id.clone(),
+                    config_source
+                        .entry
+                        .effects
+                        .inputs
+                        .iter()
+                        .map(|(name, declaration)| {
+                            (
+                                name.clone(),
+                                self.effective_input(&declaration.distant).clone(),
+                            )
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
     pub async fn write_lock(&self) {
         self.cached_fixed_input.write_lock(&self.lock_file).await;
     }
 
-    pub fn load_lock(&mut self) {
-        self.cached_fixed_input = CachedFixedInput::new_from_lock(&self.lock_file);
+    /// load the lock file. Nothing is loaded if it doesn't exist yet.
+    pub fn load_lock(&mut self) -> Result<(), LoadLockError> {
+        if self.lock_file.exists() {
+            self.cached_fixed_input = CachedFixedInput::new_from_lock(&self.lock_file)?;
+        };
+        Ok(())
+    }
+}
+
+impl SourcesState for ConfigManager {
+    fn is_source_enabled(&self, id: &str) -> bool {
+        self.get_config(id).map(|config| config.1).unwrap_or(false)
+    }
+
+    fn source_option(&self, id: &str, keyword: &str) -> Option<&ConfigValue> {
+        self.get_config(id).and_then(|config| config.2.get(keyword))
+    }
+}
+
+#[test]
+fn test_input_overrides_round_trip() {
+    let directory = crate::temp_dir::TempDir::new("input-overrides-test").unwrap();
+    let new_manager = || {
+        ConfigManager::new(
+            directory.path().join("config.json"),
+            directory.path().join("lock.json"),
+            directory.path().join("package.nix"),
+        )
+    };
+    let nixpkgs = |floating: bool| UpdatableInput::SystemWide {
+        package: "nixpkgs".to_string(),
+        floating,
+    };
+    let local = |path: &str| UpdatableInput::LocalPath {
+        path: PathBuf::from(path),
+        is_absolute: true,
+    };
+    // both rules match nixpkgs, the last added one win
+    let mut manager = new_manager();
+    manager.add_input_override(InputOverride {
+        from: nixpkgs(false),
+        to: local("/first"),
+    });
+    manager.add_input_override(InputOverride {
+        from: nixpkgs(true),
+        to: local("/second"),
+    });
+    assert_eq!(manager.effective_input(&nixpkgs(true)), &local("/second"));
+    for _ in 0..2 {
+        async_std::task::block_on(manager.save_to_config_file()).unwrap();
+        let mut loaded = new_manager();
+        loaded.load_config();
+        assert_eq!(loaded.input_overrides(), manager.input_overrides());
+        assert_eq!(loaded.effective_input(&nixpkgs(true)), &local("/second"));
+        manager = loaded;
     }
 }
+
+#[test]
+fn test_hidden_values_round_trip() {
+    let directory = crate::temp_dir::TempDir::new("hidden-values-test").unwrap();
+    let source_dir = directory.path().join("source");
+    std::fs::create_dir(&source_dir).unwrap();
+    std::fs::write(
+        source_dir.join("config.json"),
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "demo", "id": "demo", "kind": {"type": "Checkbox"}},
+                {"label": "username", "id": "username", "condition": "!demo",
+                 "when_hidden": "Null", "kind": {"type": "Textbox"}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let new_manager = || {
+        let mut manager = ConfigManager::new(
+            directory.path().join("config.json"),
+            directory.path().join("lock.json"),
+            directory.path().join("package.nix"),
+        );
+        manager
+            .add_configuration_source_from_path(source_dir.clone())
+            .unwrap();
+        manager
+    };
+    let mut manager = new_manager();
+    manager.set_configuration("test".to_string(), "username".to_string(), "me".into());
+    manager.set_configuration("test".to_string(), "demo".to_string(), true.into());
+    async_std::task::block_on(manager.save_to_config_file()).unwrap();
+
+    // the username is hidden, but what was entered is still there once reloaded
+    let mut loaded = new_manager();
+    loaded.load_config();
+    let (source, _, status) = loaded.get_config("test").unwrap();
+    assert_eq!(status.get("username"), Some(&"me".into()));
+    assert_eq!(
+        source
+            .entry
+            .values_to_apply(status, &loaded)
+            .get("username"),
+        Some(&None)
+    );
+}
+
+#[test]
+fn test_package_imported_from_its_folder() {
+    let directory = crate::temp_dir::TempDir::new("package-file-test").unwrap();
+    let source_dir = directory.path().join("source");
+    std::fs::create_dir(&source_dir).unwrap();
+    std::fs::write(
+        source_dir.join("config.json"),
+        r#"{
+            "label": "test", "id": "test", "maintainers": [], "always_enabled": true,
+            "effects": {"package": {"path": "package.nix"}}
+        }"#,
+    )
+    .unwrap();
+    std::fs::write(
+        source_dir.join("package.nix"),
+        "{ ... }: import ./other.nix",
+    )
+    .unwrap();
+    let mut manager = ConfigManager::new(
+        directory.path().join("config.json"),
+        directory.path().join("lock.json"),
+        directory.path().join("package.nix"),
+    );
+    manager
+        .add_configuration_source_from_path(source_dir.clone())
+        .unwrap();
+    let mut link_to_name = BTreeMap::new();
+    link_to_name.insert("test".to_string(), BTreeMap::new());
+    let package_file = manager
+        .generate_nix_package_file(&InputsSet::new(), &link_to_name, &mut Vec::new())
+        .unwrap();
+    // the package file isn't copied alone to the store, so it can import its siblings
+    let package_path = std::fs::canonicalize(&source_dir)
+        .unwrap()
+        .join("package.nix");
+    assert!(package_file.contains(&format!(
+        "(import (builtins.toPath {})",
+        escape_string(&package_path.to_string_lossy())
+    )));
+}
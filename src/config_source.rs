@@ -1,9 +1,13 @@
-use crate::gate::Gate;
+use crate::config_manager::UserConfiguration;
+use crate::config_value::{format_nix_float, ConfigValue};
+use crate::gate::{Gate, InvalidGate, KnownOptions, Number, SourcesState};
 
 use crate::inputs_set::InputDeclaration;
+use crate::nixtool::{escape_string, generate_dict_from_btreemap};
+use regex::Regex;
 use serde::Deserialize;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 
 use std::io;
@@ -24,6 +28,71 @@ quick_error! {
             cause(err)
             display(me) -> ("can't parse file {:?}: {}", path, err)
         }
+        InvalidGates { path: PathBuf, problems: Vec<InvalidGate> } {
+            display(me) -> ("invalid conditions in {:?}: {}", path, problems.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))
+        }
+        InvalidOptions { path: PathBuf, problems: Vec<InvalidOption> } {
+            display(me) -> ("invalid options in {:?}: {}", path, problems.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum InvalidOption {
+        NotPositiveStep { path: String, step: String } {
+            display(me) -> ("{}: the step {} should be greater than 0", path, step)
+        }
+        InvalidDefault { path: String, err: InvalidValue } {
+            cause(err)
+            display(me) -> ("{}: the default value isn't valid: {}", path, err)
+        }
+        InvalidPattern { path: String, pattern: String } {
+            display(me) -> ("{}: {:?} isn't a valid regex", path, pattern)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum UserConfigError {
+        InvalidValue { config_source: String, option: String, err: InvalidValue } {
+            cause(err)
+            display(me) -> ("invalid value for the option {:?} of {:?}: {}", option, config_source, err)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum InvalidValue {
+        NotAnInteger { value: String } {
+            display(me) -> ("{:?} isn't an integer", value)
+        }
+        NotANumber { value: String } {
+            display(me) -> ("{:?} isn't a number", value)
+        }
+        TooLow { value: String, min: String } {
+            display(me) -> ("{} is lower than the minimum {}", value, min)
+        }
+        TooHigh { value: String, max: String } {
+            display(me) -> ("{} is greater than the maximum {}", value, max)
+        }
+        NotAList { value: String } {
+            display(me) -> ("{} isn't a list of strings", value)
+        }
+        TooManyItems { count: usize, max: usize } {
+            display(me) -> ("the list has {} items, but can have at most {}", count, max)
+        }
+        InvalidItem { item: String, pattern: String } {
+            display(me) -> ("{:?} doesn't match the pattern {:?}", item, pattern)
+        }
+        InvalidPattern { pattern: String } {
+            display(me) -> ("the pattern {:?} of the option isn't a valid regex", pattern)
+        }
+        NotOnStep { value: String, step: String } {
+            display(me) -> ("{} isn't on a step of {} from the minimum", value, step)
+        }
     }
 }
 
@@ -40,17 +109,23 @@ impl ConfigSource {
     /// Create a new configuration source from a folder that contain it's data, including the
     /// root config.json
     pub fn new_from_path(folder_root: PathBuf) -> Result<Self, LoadConfigError> {
-        Ok(ConfigSource {
-            entry: ConfigEntry::new_from_path(
-                {
-                    let mut x = folder_root.clone();
-                    x.push(CONFIG_FILE_NAME);
-                    x
-                },
-                &folder_root,
-            )?,
-            folder_root,
-        })
+        let config_path = folder_root.join(CONFIG_FILE_NAME);
+        let entry = ConfigEntry::new_from_path(config_path.clone(), &folder_root)?;
+        let problems = entry.validate_gates();
+        if !problems.is_empty() {
+            return Err(LoadConfigError::InvalidGates {
+                path: config_path,
+                problems,
+            });
+        };
+        let problems = entry.validate_options();
+        if !problems.is_empty() {
+            return Err(LoadConfigError::InvalidOptions {
+                path: config_path,
+                problems,
+            });
+        };
+        Ok(ConfigSource { entry, folder_root })
     }
 }
 
@@ -79,6 +154,291 @@ pub struct ConfigEntry {
 }
 
 impl ConfigEntry {
+    /// the id of the other config sources whose exported inputs this config source use
+    pub fn required_config_sources(&self) -> BTreeSet<String> {
+        self.effects
+            .inputs
+            .values()
+            .flat_map(InputDeclaration::required_config_sources)
+            .filter(|config_source| *config_source != self.id)
+            .map(|config_source| config_source.to_string())
+            .collect()
+    }
+
+    /// every option of this config source, including the one inside groups, with the JSON path
+    /// of their definition
+    pub fn all_configurations(&self) -> Vec<(String, &Configuration)> {
+        fn collect<'a>(
+            path: &str,
+            configurations: &'a [Configuration],
+            result: &mut Vec<(String, &'a Configuration)>,
+        ) {
+            for (count, configuration) in configurations.iter().enumerate() {
+                let configuration_path = format!("{}[{}]", path, count);
+                if let ConfigurationKind::Group { configurations } = &configuration.kind {
+                    collect(
+                        &format!("{}.kind.configurations", configuration_path),
+                        configurations,
+                        result,
+                    );
+                };
+                result.push((configuration_path, configuration));
+            }
+        }
+        let mut result = Vec::new();
+        collect("configurations", &self.configurations, &mut result);
+        result
+    }
+
+    /// the options the conditions can refer to
+    pub fn known_options(&self) -> KnownOptions {
+        self.all_configurations()
+            .into_iter()
+            .filter(|(_, configuration)| configuration.kind.default_value().is_some())
+            .map(|(_, configuration)| {
+                let possibilities = match &configuration.kind {
+                    ConfigurationKind::RadioButton { possibilities, .. } => Some(
+                        possibilities
+                            .iter()
+                            .map(|possibility| possibility.id.clone())
+                            .collect(),
+                    ),
+                    _ => None,
+                };
+                (configuration.id.clone(), possibilities)
+            })
+            .collect()
+    }
+
+    /// check the condition of every option, and return the problems found
+    pub fn validate_gates(&self) -> Vec<InvalidGate> {
+        let options = self.known_options();
+        let mut problems = Vec::new();
+        for (path, configuration) in self.all_configurations() {
+            configuration.condition.validate(
+                &format!("{}.condition", path),
+                &options,
+                &mut problems,
+            );
+        }
+        problems
+    }
+
+    /// check the settings of every option, and return the problems found
+    pub fn validate_options(&self) -> Vec<InvalidOption> {
+        let mut problems = Vec::new();
+        for (path, configuration) in self.all_configurations() {
+            let step = match &configuration.kind {
+                ConfigurationKind::Integer { step, .. } if *step <= 0 => Some(step.to_string()),
+                ConfigurationKind::Float {
+                    step: Some(step), ..
+                } if step.0 <= 0.0 || step.0.is_nan() => Some(step.0.to_string()),
+                _ => None,
+            };
+            if let Some(step) = step {
+                problems.push(InvalidOption::NotPositiveStep {
+                    path: format!("{}.kind.step", path),
+                    step,
+                });
+            };
+            if let ConfigurationKind::StringList {
+                item_pattern: Some(pattern),
+                ..
+            } = &configuration.kind
+            {
+                if item_regex(pattern).is_err() {
+                    problems.push(InvalidOption::InvalidPattern {
+                        path: format!("{}.kind.item_pattern", path),
+                        pattern: pattern.clone(),
+                    });
+                    continue;
+                };
+            };
+            // the default value is used when nothing is entered, or when the option is hidden
+            if let ConfigurationKind::Integer { .. }
+            | ConfigurationKind::Float { .. }
+            | ConfigurationKind::StringList { .. } = &configuration.kind
+            {
+                if let Some(Err(err)) = configuration
+                    .kind
+                    .default_value()
+                    .map(|default| configuration.kind.check_value(&default))
+                {
+                    problems.push(InvalidOption::InvalidDefault {
+                        path: format!("{}.kind.default", path),
+                        err,
+                    });
+                };
+            };
+        }
+        problems
+    }
+
+    /// the value of every option passed to the package, according to their visibility: a hidden
+    /// option (because its condition, or the condition of a group containing it, is false) get
+    /// the value of its `when_hidden`, where `None` is a nix `null`, and an omitted option isn't
+    /// in the result. A condition that can't be evaluated count as false, like in the GUI.
+    pub fn values_to_apply(
+        &self,
+        status: &UserConfiguration,
+        sources: &dyn SourcesState,
+    ) -> BTreeMap<String, Option<ConfigValue>> {
+        fn collect(
+            configurations: &[Configuration],
+            parent_visible: bool,
+            status: &UserConfiguration,
+            sources: &dyn SourcesState,
+            result: &mut BTreeMap<String, Option<ConfigValue>>,
+        ) {
+            for configuration in configurations {
+                let visible = parent_visible
+                    && configuration
+                        .condition
+                        .evaluate(status, sources)
+                        .unwrap_or(false);
+                if let ConfigurationKind::Group { configurations } = &configuration.kind {
+                    collect(configurations, visible, status, sources, result);
+                    continue;
+                };
+                let default = configuration.kind.default_value();
+                let value = if visible {
+                    Some(status.get(&configuration.id).cloned().or(default))
+                } else {
+                    match configuration.when_hidden {
+                        HiddenValue::Omit => None,
+                        HiddenValue::Null => Some(None),
+                        HiddenValue::Default => Some(default),
+                    }
+                };
+                if let Some(value) = value {
+                    result.insert(configuration.id.clone(), value);
+                };
+            }
+        }
+        let mut result = BTreeMap::new();
+        collect(&self.configurations, true, status, sources, &mut result);
+        result
+    }
+
+    /// the `user_config` attribute set passed to the package, as nix expressions by option id.
+    /// `secrets_file` is the absolute path of the file the secrets are saved in. Fail if a value
+    /// isn't valid for its option. What the user should know about (like a secret that end up in
+    /// the nix store) is added to `warnings`.
+    pub fn user_config_nix(
+        &self,
+        status: &UserConfiguration,
+        sources: &dyn SourcesState,
+        secrets_file: &Path,
+        warnings: &mut Vec<String>,
+    ) -> Result<BTreeMap<String, String>, UserConfigError> {
+        let kinds: BTreeMap<&str, &ConfigurationKind> = self
+            .all_configurations()
+            .into_iter()
+            .map(|(_, configuration)| (configuration.id.as_str(), &configuration.kind))
+            .collect();
+        self.values_to_apply(status, sources)
+            .into_iter()
+            .map(|(id, value)| {
+                let nix_value = match (value, kinds.get(id.as_str())) {
+                    // an empty secret (or a hidden one, that get the empty default value) isn't
+                    // in the secrets file
+                    (Some(value), Some(ConfigurationKind::Secret { inline: false }))
+                        if value.is_empty() =>
+                    {
+                        "null".to_string()
+                    }
+                    (Some(_), Some(ConfigurationKind::Secret { inline: false })) => {
+                        let mut reference = BTreeMap::new();
+                        reference.insert(
+                            "file".to_string(),
+                            escape_string(&secrets_file.to_string_lossy()),
+                        );
+                        reference.insert("source".to_string(), escape_string(&self.id));
+                        reference.insert("option".to_string(), escape_string(&id));
+                        generate_dict_from_btreemap(&reference)
+                    }
+                    (Some(value), Some(ConfigurationKind::Secret { inline: true })) => {
+                        if !value.is_empty() {
+                            warnings.push(format!(
+                                "the package of {:?} need the secret {:?} itself, it is readable by every user of this computer in the nix store",
+                                self.id, id
+                            ));
+                        };
+                        value.to_nix()
+                    }
+                    (Some(value), Some(kind)) => kind.nix_value(&value).map_err(|err| {
+                        UserConfigError::InvalidValue {
+                            config_source: self.id.clone(),
+                            option: id.clone(),
+                            err,
+                        }
+                    })?,
+                    (Some(value), None) => value.to_nix(),
+                    (None, _) => "null".to_string(),
+                };
+                Ok((escape_string(&id), nix_value))
+            })
+            .collect()
+    }
+
+    /// convert the values saved before they were typed (as strings) to the type of their option,
+    /// and set the options without a value to their default value
+    pub fn migrate_values(&self, status: &mut UserConfiguration) {
+        for (_, configuration) in self.all_configurations() {
+            let value = match status.remove(&configuration.id) {
+                Some(value) => Some(configuration.kind.migrate_value(value)),
+                None => configuration.kind.default_value(),
+            };
+            if let Some(value) = value {
+                status.insert(configuration.id.clone(), value);
+            };
+        }
+    }
+
+    /// the id of the secret options
+    fn secret_ids(&self) -> BTreeSet<&str> {
+        self.all_configurations()
+            .into_iter()
+            .filter(|(_, configuration)| {
+                matches!(configuration.kind, ConfigurationKind::Secret { .. })
+            })
+            .map(|(_, configuration)| configuration.id.as_str())
+            .collect()
+    }
+
+    /// the values that should be saved in the user configuration: the entered value of every
+    /// option, even the hidden ones (their `when_hidden` is only used by `values_to_apply`), without
+    /// the secrets
+    pub fn values_to_save(&self, status: &UserConfiguration) -> UserConfiguration {
+        self.all_configurations()
+            .into_iter()
+            .filter(|(_, configuration)| {
+                !matches!(
+                    configuration.kind,
+                    ConfigurationKind::Group { .. } | ConfigurationKind::Secret { .. }
+                )
+            })
+            .filter_map(|(_, configuration)| {
+                status
+                    .get(&configuration.id)
+                    .map(|value| (configuration.id.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// the secrets that should be saved in the secrets file (the non empty ones)
+    pub fn secrets_to_save(&self, status: &UserConfiguration) -> BTreeMap<String, String> {
+        self.secret_ids()
+            .into_iter()
+            .filter_map(|id| {
+                status
+                    .get(id)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (id.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+
     pub fn new_from_path(config_path: PathBuf, root_dir: &Path) -> Result<Self, LoadConfigError> {
         let configuration_file =
             File::open(&config_path).map_err(|err| LoadConfigError::CantReadFile {
@@ -113,10 +473,32 @@ pub struct Configuration {
     /// the condition for this option to be displayed
     #[serde(default = "Gate::default")]
     pub condition: Gate,
+    /// the value passed to the package when this option is hidden by its condition
+    #[serde(default = "HiddenValue::default")]
+    pub when_hidden: HiddenValue,
     /// kind specific information
     pub kind: ConfigurationKind,
 }
 
+/// What is passed to the package for an option hidden by its condition, instead of the value
+/// entered before it was hidden
+#[derive(Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum HiddenValue {
+    /// the option isn't passed at all
+    Omit,
+    /// the option is passed as `null`
+    Null,
+    /// the default value of the option is passed
+    Default,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for HiddenValue {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Hash)]
 #[serde(tag = "type")]
 pub enum ConfigurationKind {
@@ -135,26 +517,237 @@ pub enum ConfigurationKind {
         #[serde(default = "String::new")]
         default: String,
     },
+    /// a secret, like a password or a token, entered in a masked field. It is saved in a separate
+    /// file only readable by the user, and the package get a reference to it instead of its
+    /// value: `{ file = "/path/to/secrets.json"; source = "<config source id>"; option = "<option
+    /// id>"; }`, the secret being the string at `.<source>.<option>` of this JSON file (or `null`
+    /// if the secret is empty). If `inline` is set, the package get the secret itself, which end up in the nix store, where
+    /// it is readable by every user.
+    Secret {
+        #[serde(default = "bool::default")]
+        inline: bool,
+    },
     Group {
         configurations: Vec<Configuration>,
     },
+    /// a list of strings, passed as a nix list. If set, every item should fully match the regex
+    /// `item_pattern`, and there should be at most `max_length` items.
+    StringList {
+        #[serde(default = "Vec::default")]
+        default: Vec<String>,
+        item_pattern: Option<String>,
+        max_length: Option<usize>,
+    },
+    /// an integer, passed as a nix integer. `step` is the increment of the slider, displayed when
+    /// both `min` and `max` are set.
+    Integer {
+        #[serde(default = "i64::default")]
+        default: i64,
+        min: Option<i64>,
+        max: Option<i64>,
+        #[serde(default = "default_integer_step")]
+        step: i64,
+    },
+    /// a floating point number, passed as a nix float. `step` is the increment of the slider,
+    /// displayed when both `min` and `max` are set (a hundredth of the range by default).
+    Float {
+        #[serde(default = "Number::default")]
+        default: Number,
+        min: Option<Number>,
+        max: Option<Number>,
+        step: Option<Number>,
+    },
+}
+
+fn default_integer_step() -> i64 {
+    1
+}
+
+/// the regex an item of a StringList should fully match
+fn item_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+/// check that `value` is in the range, if it is bounded
+fn check_range<T: PartialOrd + ToString>(
+    value: T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), InvalidValue> {
+    if let Some(min) = min {
+        if value < min {
+            return Err(InvalidValue::TooLow {
+                value: value.to_string(),
+                min: min.to_string(),
+            });
+        };
+    };
+    if let Some(max) = max {
+        if value > max {
+            return Err(InvalidValue::TooHigh {
+                value: value.to_string(),
+                max: max.to_string(),
+            });
+        };
+    };
+    Ok(())
 }
 
 impl ConfigurationKind {
-    /// return the default value of this configuration kind, as a string. return none if it doesn't
-    /// need to save anything.
-    pub fn default_value(&self) -> Option<String> {
+    /// return the default value of this configuration kind. return none if it doesn't need to
+    /// save anything.
+    pub fn default_value(&self) -> Option<ConfigValue> {
         match self {
-            Self::Checkbox { default } => Some(if *default {
-                "true".to_string()
-            } else {
-                "false".to_string()
-            }),
-            Self::RadioButton { default, .. } => Some(default.clone()),
-            Self::Textbox { default, .. } => Some(default.clone()),
+            Self::Checkbox { default } => Some(ConfigValue::Bool(*default)),
+            Self::RadioButton { default, .. } => Some(ConfigValue::String(default.clone())),
+            Self::Textbox { default, .. } => Some(ConfigValue::String(default.clone())),
+            Self::Secret { .. } => Some(ConfigValue::String(String::new())),
             Self::Group { .. } => None,
+            Self::Integer { default, .. } => Some(ConfigValue::Integer(*default)),
+            Self::Float { default, .. } => Some(ConfigValue::Float(default.0)),
+            Self::StringList { default, .. } => Some(ConfigValue::List(
+                default.iter().cloned().map(ConfigValue::String).collect(),
+            )),
         }
     }
+
+    /// parse the text entered by the user for this kind of option
+    pub fn parse_value(&self, text: &str) -> Result<ConfigValue, InvalidValue> {
+        let value = match self {
+            Self::Integer { .. } => ConfigValue::Integer(text.trim().parse().map_err(|_| {
+                InvalidValue::NotAnInteger {
+                    value: text.to_string(),
+                }
+            })?),
+            Self::Float { .. } => ConfigValue::Float(
+                text.trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|parsed| parsed.is_finite())
+                    .ok_or_else(|| InvalidValue::NotANumber {
+                        value: text.to_string(),
+                    })?,
+            ),
+            Self::Checkbox { .. } => ConfigValue::Bool(text.trim() == "true"),
+            _ => ConfigValue::String(text.to_string()),
+        };
+        self.check_value(&value)?;
+        Ok(value)
+    }
+
+    /// check that a value is valid for this kind of option
+    pub fn check_value(&self, value: &ConfigValue) -> Result<(), InvalidValue> {
+        match (self, value) {
+            (Self::Integer { min, max, step, .. }, ConfigValue::Integer(value)) => {
+                check_range(*value, *min, *max)?;
+                let from = min.unwrap_or(0);
+                if *step > 0 && (*value as i128 - from as i128) % *step as i128 != 0 {
+                    return Err(InvalidValue::NotOnStep {
+                        value: value.to_string(),
+                        step: step.to_string(),
+                    });
+                };
+                Ok(())
+            }
+            (Self::Integer { .. }, value) => Err(InvalidValue::NotAnInteger {
+                value: value.to_string(),
+            }),
+            (Self::Float { min, max, step, .. }, ConfigValue::Integer(_))
+            | (Self::Float { min, max, step, .. }, ConfigValue::Float(_)) => match value.as_f64() {
+                Some(value) if value.is_finite() => {
+                    check_range(value, min.map(|min| min.0), max.map(|max| max.0))?;
+                    if let Some(step) = step.filter(|step| step.0 > 0.0) {
+                        // allow for the rounding errors of the slider
+                        let steps = (value - min.map(|min| min.0).unwrap_or(0.0)) / step.0;
+                        if (steps - steps.round()).abs() > 1e-9 * steps.abs().max(1.0) {
+                            return Err(InvalidValue::NotOnStep {
+                                value: value.to_string(),
+                                step: step.0.to_string(),
+                            });
+                        };
+                    };
+                    Ok(())
+                }
+                _ => Err(InvalidValue::NotANumber {
+                    value: value.to_string(),
+                }),
+            },
+            (Self::Float { .. }, value) => Err(InvalidValue::NotANumber {
+                value: value.to_string(),
+            }),
+            (
+                Self::StringList {
+                    item_pattern,
+                    max_length,
+                    ..
+                },
+                ConfigValue::List(items),
+            ) => {
+                if let Some(max) = max_length {
+                    if items.len() > *max {
+                        return Err(InvalidValue::TooManyItems {
+                            count: items.len(),
+                            max: *max,
+                        });
+                    };
+                };
+                let pattern = match item_pattern {
+                    Some(pattern) => Some((
+                        pattern,
+                        item_regex(pattern).map_err(|_| InvalidValue::InvalidPattern {
+                            pattern: pattern.clone(),
+                        })?,
+                    )),
+                    None => None,
+                };
+                for item in items {
+                    let item = match item {
+                        ConfigValue::String(item) => item,
+                        _ => {
+                            return Err(InvalidValue::NotAList {
+                                value: value.to_string(),
+                            })
+                        }
+                    };
+                    if let Some((pattern, regex)) = &pattern {
+                        if !regex.is_match(item) {
+                            return Err(InvalidValue::InvalidItem {
+                                item: item.clone(),
+                                pattern: pattern.to_string(),
+                            });
+                        };
+                    };
+                }
+                Ok(())
+            }
+            (Self::StringList { .. }, value) => Err(InvalidValue::NotAList {
+                value: value.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// convert a value saved before the values were typed (when everything was a string) to the
+    /// type of this kind of option
+    pub fn migrate_value(&self, value: ConfigValue) -> ConfigValue {
+        match (self, &value) {
+            (Self::Checkbox { .. }, ConfigValue::String(text))
+            | (Self::Integer { .. }, ConfigValue::String(text))
+            | (Self::Float { .. }, ConfigValue::String(text)) => {
+                self.parse_value(text).unwrap_or(value)
+            }
+            _ => value,
+        }
+    }
+
+    /// the nix expression of a value of this kind of option, if it is valid
+    pub fn nix_value(&self, value: &ConfigValue) -> Result<String, InvalidValue> {
+        self.check_value(value)?;
+        Ok(match (self, value) {
+            (Self::Float { .. }, ConfigValue::Integer(value)) => format_nix_float(*value as f64),
+            _ => value.to_nix(),
+        })
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, Hash)]
@@ -174,3 +767,333 @@ pub struct Effects {
 pub struct PackageEffect {
     pub path: String,
 }
+
+#[test]
+fn test_validate_gates() {
+    let entry: ConfigEntry = serde_json::from_str(
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "release", "id": "release", "kind": {
+                    "type": "RadioButton", "default": "alpha",
+                    "possibilities": [{"label": "alpha", "id": "alpha"}]
+                }},
+                {"label": "group", "id": "group", "kind": {"type": "Group", "configurations": [
+                    {"label": "token", "id": "token", "kind": {"type": "Textbox"},
+                     "condition": {"type": "KeywordEqual", "keyword": "release", "equal_to": "demo"}}
+                ]}},
+                {"label": "name", "id": "name", "kind": {"type": "Textbox"},
+                 "condition": {"type": "KeywordTrue", "keyword": "token"}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let problems = entry.validate_gates();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(
+        problems[0].to_string(),
+        "configurations[1].kind.configurations[0].condition: \"demo\" isn't a possibility of the option \"release\""
+    );
+}
+
+#[test]
+fn test_values_to_apply() {
+    use crate::gate::NoSources;
+    let entry: ConfigEntry = serde_json::from_str(
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "demo", "id": "demo", "kind": {"type": "Checkbox"}},
+                {"label": "group", "id": "group", "condition": "!demo", "kind": {"type": "Group", "configurations": [
+                    {"label": "username", "id": "username", "when_hidden": "Null", "kind": {"type": "Textbox"}},
+                    {"label": "token", "id": "token", "when_hidden": "Omit", "kind": {"type": "Textbox"}}
+                ]}},
+                {"label": "name", "id": "name", "condition": "!demo",
+                 "kind": {"type": "Textbox", "default": "player"}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut status = UserConfiguration::new();
+    for (id, value) in &[("username", "me"), ("token", "secret"), ("name", "other")] {
+        status.insert(id.to_string(), (*value).into());
+    }
+    status.insert("demo".to_string(), false.into());
+    let values = entry.values_to_apply(&status, &NoSources);
+    assert_eq!(values.get("token"), Some(&Some("secret".into())));
+    assert_eq!(values.get("name"), Some(&Some("other".into())));
+
+    status.insert("demo".to_string(), true.into());
+    let values = entry.values_to_apply(&status, &NoSources);
+    assert_eq!(values.get("username"), Some(&None));
+    assert_eq!(values.get("token"), None);
+    assert_eq!(values.get("name"), Some(&Some("player".into())));
+    assert_eq!(values.get("demo"), Some(&Some(true.into())));
+    // the entered values of the hidden options are kept
+    let saved = entry.values_to_save(&status);
+    assert_eq!(saved, status);
+}
+
+#[test]
+fn test_numeric_options() {
+    let kind = |json: &str| -> ConfigurationKind { serde_json::from_str(json).unwrap() };
+    let port = kind(r#"{"type": "Integer", "default": 34197, "min": 1, "max": 65535}"#);
+    assert_eq!(port.parse_value(" 8080 "), Ok(ConfigValue::Integer(8080)));
+    assert_eq!(
+        port.parse_value("80.5"),
+        Err(InvalidValue::NotAnInteger {
+            value: "80.5".to_string()
+        })
+    );
+    assert!(matches!(
+        port.parse_value("70000"),
+        Err(InvalidValue::TooHigh { .. })
+    ));
+    assert_eq!(
+        port.nix_value(&ConfigValue::Integer(8080)),
+        Ok("8080".to_string())
+    );
+    assert!(matches!(
+        port.nix_value(&ConfigValue::Integer(0)),
+        Err(InvalidValue::TooLow { .. })
+    ));
+    assert!(matches!(
+        port.nix_value(&"8080".into()),
+        Err(InvalidValue::NotAnInteger { .. })
+    ));
+
+    let players = kind(r#"{"type": "Integer", "default": 2, "min": 2, "max": 20, "step": 2}"#);
+    assert_eq!(players.check_value(&ConfigValue::Integer(8)), Ok(()));
+    assert_eq!(
+        players.parse_value("7"),
+        Err(InvalidValue::NotOnStep {
+            value: "7".to_string(),
+            step: "2".to_string()
+        })
+    );
+
+    let ratio = kind(r#"{"type": "Float", "default": 0.5, "max": 2}"#);
+    assert_eq!(ratio.default_value(), Some(ConfigValue::Float(0.5)));
+    assert_eq!(ratio.parse_value("-1e3"), Ok(ConfigValue::Float(-1000.0)));
+    assert!(matches!(
+        ratio.parse_value("inf"),
+        Err(InvalidValue::NotANumber { .. })
+    ));
+    assert_eq!(
+        ratio.nix_value(&ConfigValue::Integer(2)),
+        Ok("2.0".to_string())
+    );
+    assert_eq!(
+        ratio.nix_value(&ConfigValue::Float(1.25)),
+        Ok("1.25".to_string())
+    );
+    assert!(matches!(
+        ratio.nix_value(&ConfigValue::Float(3.0)),
+        Err(InvalidValue::TooHigh { .. })
+    ));
+
+    let volume = kind(r#"{"type": "Float", "min": 0.5, "max": 1.5, "step": 0.1}"#);
+    assert_eq!(volume.check_value(&ConfigValue::Float(0.7)), Ok(()));
+    assert!(matches!(
+        volume.check_value(&ConfigValue::Float(0.75)),
+        Err(InvalidValue::NotOnStep { .. })
+    ));
+}
+
+#[test]
+fn test_migrate_values() {
+    let entry: ConfigEntry = serde_json::from_str(
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "stable", "id": "stable", "kind": {"type": "Checkbox"}},
+                {"label": "players", "id": "players", "kind": {"type": "Integer", "default": 4}},
+                {"label": "name", "id": "name", "kind": {"type": "Textbox"}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    // a configuration saved before the values were typed
+    let mut status: UserConfiguration =
+        serde_json::from_str(r#"{"stable": "true", "name": "8"}"#).unwrap();
+    entry.migrate_values(&mut status);
+    assert_eq!(status["stable"], ConfigValue::Bool(true));
+    assert_eq!(status["players"], ConfigValue::Integer(4));
+    assert_eq!(status["name"], ConfigValue::String("8".to_string()));
+}
+
+#[test]
+fn test_secrets() {
+    use crate::gate::NoSources;
+    let entry: ConfigEntry = serde_json::from_str(
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "name", "id": "name", "kind": {"type": "Textbox"}},
+                {"label": "password", "id": "password", "kind": {"type": "Secret"}},
+                {"label": "token", "id": "token", "kind": {"type": "Secret", "inline": true}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut status = UserConfiguration::new();
+    status.insert("name".to_string(), "me".into());
+    status.insert("password".to_string(), "hunter2".into());
+    status.insert("token".to_string(), "".into());
+    assert_eq!(
+        entry.values_to_save(&status).keys().collect::<Vec<_>>(),
+        vec!["name"]
+    );
+    let secrets = entry.secrets_to_save(&status);
+    assert_eq!(secrets.len(), 1);
+    assert_eq!(secrets["password"], "hunter2");
+
+    let mut warnings = Vec::new();
+    let user_config = entry
+        .user_config_nix(
+            &status,
+            &NoSources,
+            Path::new("/secrets.json"),
+            &mut warnings,
+        )
+        .unwrap();
+    assert_eq!(
+        user_config["\"password\""],
+        "{\nfile = \"/secrets.json\";\noption = \"password\";\nsource = \"test\";\n}"
+    );
+    assert!(!user_config.values().any(|value| value.contains("hunter2")));
+    assert_eq!(user_config["\"token\""], "\"\"");
+    // the empty inline secret isn't in the nix store
+    assert!(warnings.is_empty());
+
+    // an empty secret isn't in the secrets file, so there is nothing to refer to
+    status.insert("password".to_string(), "".into());
+    let user_config = entry
+        .user_config_nix(
+            &status,
+            &NoSources,
+            Path::new("/secrets.json"),
+            &mut warnings,
+        )
+        .unwrap();
+    assert_eq!(user_config["\"password\""], "null");
+
+    // the user is warned about the inline secret put in the nix store
+    status.insert("token".to_string(), "abc".into());
+    entry
+        .user_config_nix(
+            &status,
+            &NoSources,
+            Path::new("/secrets.json"),
+            &mut warnings,
+        )
+        .unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("\"token\""));
+}
+
+#[test]
+fn test_string_list() {
+    let kind: ConfigurationKind = serde_json::from_str(
+        r#"{"type": "StringList", "default": ["mesecons"], "item_pattern": "[a-z_]+", "max_length": 2}"#,
+    )
+    .unwrap();
+    let list = |items: &[&str]| ConfigValue::List(items.iter().map(|&item| item.into()).collect());
+    assert_eq!(kind.default_value(), Some(list(&["mesecons"])));
+    assert_eq!(kind.check_value(&list(&["mesecons", "pipeworks"])), Ok(()));
+    assert_eq!(
+        kind.check_value(&list(&["a", "b", "c"])),
+        Err(InvalidValue::TooManyItems { count: 3, max: 2 })
+    );
+    assert!(matches!(
+        kind.check_value(&list(&["mesecons", "has space"])),
+        Err(InvalidValue::InvalidItem { item, .. }) if item == "has space"
+    ));
+    assert!(matches!(
+        kind.check_value(&"mesecons".into()),
+        Err(InvalidValue::NotAList { .. })
+    ));
+    assert_eq!(
+        kind.nix_value(&list(&["mesecons", "pipeworks"])),
+        Ok("[ \"mesecons\" \"pipeworks\" ]".to_string())
+    );
+    assert!(matches!(
+        kind.nix_value(&list(&["Invalid"])),
+        Err(InvalidValue::InvalidItem { .. })
+    ));
+
+    // a list with an invalid pattern is reported instead of being replaced by the default value
+    let kind: ConfigurationKind =
+        serde_json::from_str(r#"{"type": "StringList", "item_pattern": "[a-z"}"#).unwrap();
+    assert_eq!(
+        kind.nix_value(&list(&["mesecons"])),
+        Err(InvalidValue::InvalidPattern {
+            pattern: "[a-z".to_string()
+        })
+    );
+}
+
+#[test]
+fn test_validate_options() {
+    let entry: ConfigEntry = serde_json::from_str(
+        r#"{
+            "label": "test", "id": "test", "maintainers": [],
+            "configurations": [
+                {"label": "players", "id": "players", "kind": {"type": "Integer", "step": 0}},
+                {"label": "port", "id": "port", "kind": {"type": "Integer", "min": 1}},
+                {"label": "group", "id": "group", "kind": {"type": "Group", "configurations": [
+                    {"label": "ratio", "id": "ratio", "kind": {"type": "Float", "step": -0.5}},
+                    {"label": "volume", "id": "volume", "kind": {"type": "Float", "step": 0.5}},
+                    {"label": "speed", "id": "speed", "kind": {"type": "Float", "min": 0.5, "default": 1, "step": 0.25}},
+                    {"label": "zoom", "id": "zoom", "kind": {"type": "Float", "min": 0.5, "step": 0.3, "default": 1}}
+                ]}},
+                {"label": "mods", "id": "mods", "kind": {"type": "StringList", "item_pattern": "[a-z"}},
+                {"label": "maps", "id": "maps", "kind": {"type": "StringList", "item_pattern": "[a-z]+", "default": ["Big"]}},
+                {"label": "tags", "id": "tags", "kind": {"type": "StringList", "max_length": 1, "default": ["a", "b"]}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        entry.validate_options(),
+        vec![
+            InvalidOption::NotPositiveStep {
+                path: "configurations[0].kind.step".to_string(),
+                step: "0".to_string()
+            },
+            InvalidOption::InvalidDefault {
+                path: "configurations[1].kind.default".to_string(),
+                err: InvalidValue::TooLow {
+                    value: "0".to_string(),
+                    min: "1".to_string()
+                }
+            },
+            InvalidOption::NotPositiveStep {
+                path: "configurations[2].kind.configurations[0].kind.step".to_string(),
+                step: "-0.5".to_string()
+            },
+            InvalidOption::InvalidDefault {
+                path: "configurations[2].kind.configurations[3].kind.default".to_string(),
+                err: InvalidValue::NotOnStep {
+                    value: "1".to_string(),
+                    step: "0.3".to_string()
+                }
+            },
+            InvalidOption::InvalidPattern {
+                path: "configurations[3].kind.item_pattern".to_string(),
+                pattern: "[a-z".to_string()
+            },
+            InvalidOption::InvalidDefault {
+                path: "configurations[4].kind.default".to_string(),
+                err: InvalidValue::InvalidItem {
+                    item: "Big".to_string(),
+                    pattern: "[a-z]+".to_string()
+                }
+            },
+            InvalidOption::InvalidDefault {
+                path: "configurations[5].kind.default".to_string(),
+                err: InvalidValue::TooManyItems { count: 2, max: 1 }
+            },
+        ]
+    );
+}
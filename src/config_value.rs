@@ -0,0 +1,146 @@
+use crate::nixtool::{escape_string, generate_dict_from_btreemap, to_nix_vec};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// The value of an option set by the user. It is stored in the JSON type matching its kind, so
+/// packages get real nix booleans, numbers and lists.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<ConfigValue>),
+    AttrSet(BTreeMap<String, ConfigValue>),
+}
+
+impl Hash for ConfigValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Bool(value) => value.hash(state),
+            Self::Integer(value) => value.hash(state),
+            Self::Float(value) => value.to_bits().hash(state),
+            Self::String(value) => value.hash(state),
+            Self::List(values) => values.hash(state),
+            Self::AttrSet(values) => values.hash(state),
+        }
+    }
+}
+
+/// format a float so it is a float in nix too (nix read `2` as an integer, but `2.0` as a float)
+pub fn format_nix_float(value: f64) -> String {
+    let formatted = value.to_string();
+    if formatted.contains('.') {
+        formatted
+    } else {
+        format!("{}.0", formatted)
+    }
+}
+
+impl ConfigValue {
+    /// true if it is the boolean true. Also accept the string "true" for the values saved before
+    /// they were typed.
+    pub fn is_true(&self) -> bool {
+        match self {
+            Self::Bool(value) => *value,
+            Self::String(value) => value == "true",
+            _ => false,
+        }
+    }
+
+    /// the value as a number, if it is one (or a string containing one)
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Integer(value) => Some(*value as f64),
+            Self::Float(value) => Some(*value),
+            Self::String(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// true for an empty string, list or attribute set
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::String(value) => value.is_empty(),
+            Self::List(values) => values.is_empty(),
+            Self::AttrSet(values) => values.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// the nix expression of this value
+    pub fn to_nix(&self) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer(value) => value.to_string(),
+            Self::Float(value) => format_nix_float(*value),
+            Self::String(value) => escape_string(value),
+            Self::List(values) => to_nix_vec(
+                &values
+                    .iter()
+                    .map(|value| value.to_nix())
+                    .collect::<Vec<_>>(),
+            ),
+            Self::AttrSet(values) => generate_dict_from_btreemap(
+                &values
+                    .iter()
+                    .map(|(key, value)| (escape_string(key), value.to_nix()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// the text of the value, as compared in the conditions: strings are displayed as is, and lists
+/// and attribute sets in JSON
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+            Self::List(_) | Self::AttrSet(_) => {
+                write!(f, "{}", serde_json::to_string(self).unwrap())
+            }
+        }
+    }
+}
+
+impl From<bool> for ConfigValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<&str> for ConfigValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for ConfigValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+#[test]
+fn test_config_value() {
+    let values: BTreeMap<String, ConfigValue> = serde_json::from_str(
+        r#"{"stable": true, "port": 8080, "ratio": 2.0, "name": "me", "mods": ["a", "b"], "extra": {"x": 1}}"#,
+    )
+    .unwrap();
+    assert_eq!(values["stable"], ConfigValue::Bool(true));
+    assert_eq!(values["port"], ConfigValue::Integer(8080));
+    assert_eq!(values["ratio"], ConfigValue::Float(2.0));
+    assert_eq!(values["ratio"].to_nix(), "2.0");
+    assert_eq!(values["mods"].to_nix(), "[ \"a\" \"b\" ]");
+    assert_eq!(values["extra"].to_nix(), "{\n\"x\" = 1;\n}");
+    assert_eq!(values["mods"].to_string(), "[\"a\",\"b\"]");
+    assert_eq!(serde_json::to_string(&values["stable"]).unwrap(), "true");
+}
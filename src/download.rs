@@ -0,0 +1,148 @@
+use flate2::read::GzDecoder;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum DownloadError {
+        Request { url: String, err: Box<ureq::Error> } {
+            cause(err)
+            display(me) -> ("can't download {:?}: {}", url, err)
+        }
+        CantReadBody { url: String, err: io::Error } {
+            cause(err)
+            display(me) -> ("can't read the content of {:?}: {}", url, err)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum UnpackError {
+        UnsupportedCompression {
+            display("the archive use an unsupported compression (only gzip and uncompressed tar are supported)")
+        }
+        CantUnpack { err: io::Error } {
+            cause(err)
+            display(me) -> ("can't unpack the archive: {}", err)
+        }
+    }
+}
+
+/// Download the content of the given url
+pub async fn download(url: &str) -> Result<Vec<u8>, DownloadError> {
+    let url = url.to_string();
+    async_std::task::spawn_blocking(move || {
+        let response = ureq::get(&url)
+            .call()
+            .map_err(|err| DownloadError::Request {
+                url: url.clone(),
+                err: Box::new(err),
+            })?;
+        let mut content = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut content)
+            .map_err(|err| DownloadError::CantReadBody { url, err })?;
+        Ok(content)
+    })
+    .await
+}
+
+/// Unpack a (possibly gzipped) tar archive in `destination`, and return the path that
+/// `builtins.fetchTarball` would use: the single top-level entry if it is a directory, or the
+/// destination folder otherwise.
+pub fn unpack_tarball(content: &[u8], destination: &Path) -> Result<PathBuf, UnpackError> {
+    let reader: Box<dyn Read> = if content.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(content))
+    } else if content.len() > 262 && &content[257..262] == b"ustar" {
+        Box::new(content)
+    } else {
+        return Err(UnpackError::UnsupportedCompression);
+    };
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive
+        .unpack(destination)
+        .map_err(|err| UnpackError::CantUnpack { err })?;
+    let mut entries = std::fs::read_dir(destination)
+        .map_err(|err| UnpackError::CantUnpack { err })?
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|err| UnpackError::CantUnpack { err })?;
+    if entries.len() == 1 && entries[0].path().is_dir() {
+        Ok(entries.pop().unwrap().path())
+    } else {
+        Ok(destination.to_path_buf())
+    }
+}
+
+/// Serve `content` once on a random local port, and return the url to it
+#[cfg(test)]
+pub fn serve_once(content: Vec<u8>) -> String {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/archive", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            content.len()
+        )
+        .unwrap();
+        stream.write_all(&content).unwrap();
+    });
+    url
+}
+
+#[cfg(test)]
+pub fn create_test_tarball() -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    ));
+    let content = b"{ }: 1";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "source/default.nix", &content[..])
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[test]
+fn test_download_and_unpack() {
+    let tarball = create_test_tarball();
+    let url = serve_once(tarball.clone());
+    let downloaded = async_std::task::block_on(download(&url)).unwrap();
+    assert_eq!(downloaded, tarball);
+    let destination = crate::temp_dir::TempDir::new("unpack-test").unwrap();
+    let root = unpack_tarball(&downloaded, destination.path()).unwrap();
+    assert_eq!(root, destination.path().join("source"));
+    assert_eq!(
+        std::fs::read_to_string(root.join("default.nix")).unwrap(),
+        "{ }: 1"
+    );
+    assert!(unpack_tarball(b"not an archive", destination.path()).is_err());
+
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(1);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "default.nix", &b"1"[..])
+        .unwrap();
+    let single_file = crate::temp_dir::TempDir::new("unpack-single-file-test").unwrap();
+    let root = unpack_tarball(&builder.into_inner().unwrap(), single_file.path()).unwrap();
+    assert_eq!(root, single_file.path());
+}
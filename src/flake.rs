@@ -0,0 +1,379 @@
+//! Resolution of flake references to locked ones, like `nix flake metadata` does. Local path and
+//! git flakes are resolved without nix (and without network access), other flakes are resolved
+//! by running nix.
+use crate::git::{checkout_tree, fetch_reference, GitError, TempRepository};
+use crate::nar::{nar_sha256, to_sri_sha256};
+use crate::temp_dir::TempDir;
+use async_std::process::Command;
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum FlakeError {
+        InvalidReference { reference: String } {
+            display(me) -> ("{:?} isn't a valid flake reference (expected something like \"path:/src/foo\" or \"git+file:///repo?ref=main\")", reference)
+        }
+        RelativePath { reference: String } {
+            display(me) -> ("the path of the flake {:?} isn't absolute", reference)
+        }
+        MissingFlakeFile { reference: String, dir: String } {
+            display(me) -> ("the flake {:?} doesn't have a flake.nix in the directory {:?}", reference, dir)
+        }
+        CantHash { path: PathBuf, err: io::Error } {
+            cause(err)
+            display(me) -> ("can't compute the hash of {:?}: {}", path, err)
+        }
+        Git(err: GitError) {
+            from()
+            cause(err)
+            display(me) -> ("{}", err)
+        }
+        CantRunNix { err: io::Error } {
+            cause(err)
+            display(me) -> ("can't run nix: {}", err)
+        }
+        NixFailed { stderr: String } {
+            display(me) -> ("nix flake metadata failed: {}", stderr.trim())
+        }
+        CantParseMetadata { err: serde_json::Error } {
+            cause(err)
+            display(me) -> ("can't parse the output of nix flake metadata: {}", err)
+        }
+        MissingMetadata { field: String } {
+            display(me) -> ("nix flake metadata didn't return the {} of the flake", field)
+        }
+    }
+}
+
+/// A flake reference in the url-like format, like `git+file:///repo?ref=main`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlakeReference {
+    /// the type of the flake, like `path` or `git+file`
+    pub scheme: String,
+    /// what come after the scheme, without the parameters
+    pub location: String,
+    /// the parameters of the query, percent-decoded
+    pub parameters: Vec<(String, String)>,
+}
+
+/// decode the `%XX` escapes of an url component. Invalid escapes are kept as is.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+    while position < bytes.len() {
+        let escaped = bytes
+            .get(position + 1..position + 3)
+            .filter(|_| bytes[position] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                position += 3;
+            }
+            None => {
+                result.push(bytes[position]);
+                position += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// escape the characters of an url query value that aren't unreserved (like the `+`, `/` and `=`
+/// of a base64 hash) as `%XX`. The `/` is kept, as it is common in paths.
+fn percent_encode(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    result
+}
+
+impl FlakeReference {
+    pub fn parse(reference: &str) -> Result<Self, FlakeError> {
+        let (scheme, rest) = reference
+            .split_once(':')
+            .filter(|(scheme, rest)| !scheme.is_empty() && !rest.is_empty())
+            .ok_or_else(|| FlakeError::InvalidReference {
+                reference: reference.to_string(),
+            })?;
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let parameters = query
+            .split('&')
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| match parameter.split_once('=') {
+                Some((key, value)) => (percent_decode(key), percent_decode(value)),
+                None => (percent_decode(parameter), String::new()),
+            })
+            .collect();
+        Ok(Self {
+            scheme: scheme.to_string(),
+            location: location.to_string(),
+            parameters,
+        })
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: &str) {
+        match self.parameters.iter_mut().find(|(key, _)| key == name) {
+            Some(parameter) => parameter.1 = value.to_string(),
+            None => self.parameters.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// the path of a local flake (a `path:` or `git+file:` flake), None for other flakes
+    pub fn local_path(&self) -> Option<PathBuf> {
+        match self.scheme.as_str() {
+            "path" => Some(PathBuf::from(&self.location)),
+            "git+file" => Some(PathBuf::from(
+                self.location.strip_prefix("//").unwrap_or(&self.location),
+            )),
+            _ => None,
+        }
+    }
+
+    /// change the path of a local flake
+    pub fn set_local_path(&mut self, path: &Path) {
+        let path = path.to_string_lossy();
+        match self.scheme.as_str() {
+            "path" => self.location = path.to_string(),
+            "git+file" => self.location = format!("//{}", path),
+            _ => (),
+        }
+    }
+}
+
+impl fmt::Display for FlakeReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.scheme, self.location)?;
+        for (count, (key, value)) in self.parameters.iter().enumerate() {
+            let separator = if count == 0 { '?' } else { '&' };
+            write!(
+                f,
+                "{}{}={}",
+                separator,
+                percent_encode(key),
+                percent_encode(value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A flake reference locked to a precise version of the flake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedFlake {
+    /// the locked reference, including the narHash
+    pub url: String,
+    /// the sha256 of the NAR serialisation of the flake, in the SRI format
+    pub nar_hash: String,
+}
+
+/// Lock a flake reference to the current version of the flake
+pub async fn lock_flake(reference: &str) -> Result<LockedFlake, FlakeError> {
+    let mut parsed = FlakeReference::parse(reference)?;
+    let path = match parsed.local_path() {
+        Some(path) if path.is_relative() => {
+            return Err(FlakeError::RelativePath {
+                reference: reference.to_string(),
+            })
+        }
+        Some(path) => path,
+        None => return lock_with_nix(reference).await,
+    };
+    parsed.parameters.retain(|(key, _)| key != "narHash");
+    let nar_hash = if parsed.scheme == "git+file" {
+        let repository = TempRepository::new().await?;
+        let url = format!("file://{}", path.to_string_lossy());
+        let git_reference = parsed
+            .parameter("rev")
+            .or_else(|| parsed.parameter("ref"))
+            .unwrap_or("HEAD")
+            .to_string();
+        let resolved = fetch_reference(&repository, &url, &git_reference).await?;
+        let checkout = TempDir::new("flake").map_err(|err| FlakeError::CantHash {
+            path: std::env::temp_dir(),
+            err,
+        })?;
+        checkout_tree(&repository, &resolved.rev, checkout.path()).await?;
+        check_flake_file(reference, &parsed, checkout.path())?;
+        parsed.set_parameter("rev", &resolved.rev);
+        hash_path(checkout.path().to_path_buf()).await?
+    } else {
+        check_flake_file(reference, &parsed, &path)?;
+        hash_path(path).await?
+    };
+    parsed.set_parameter("narHash", &nar_hash);
+    Ok(LockedFlake {
+        url: parsed.to_string(),
+        nar_hash,
+    })
+}
+
+/// check that the flake is in the `dir` subdirectory of `root` (or in `root` itself if unset). The
+/// narHash is still the one of the whole `root`, like nix does.
+fn check_flake_file(
+    reference: &str,
+    parsed: &FlakeReference,
+    root: &Path,
+) -> Result<(), FlakeError> {
+    let dir = parsed.parameter("dir").unwrap_or("");
+    let escape_root = Path::new(dir)
+        .components()
+        .any(|component| !matches!(component, std::path::Component::Normal(_)));
+    if escape_root || !root.join(dir).join("flake.nix").is_file() {
+        return Err(FlakeError::MissingFlakeFile {
+            reference: reference.to_string(),
+            dir: dir.to_string(),
+        });
+    };
+    Ok(())
+}
+
+async fn hash_path(path: PathBuf) -> Result<String, FlakeError> {
+    async_std::task::spawn_blocking(move || {
+        nar_sha256(&path)
+            .map(|hash| to_sri_sha256(&hash))
+            .map_err(|err| FlakeError::CantHash { path, err })
+    })
+    .await
+}
+
+/// Lock a flake with `nix flake metadata`, for the flake types that aren't handled natively
+async fn lock_with_nix(reference: &str) -> Result<LockedFlake, FlakeError> {
+    let output = Command::new("nix")
+        .args([
+            "--extra-experimental-features",
+            "nix-command flakes",
+            "flake",
+            "metadata",
+            "--json",
+            reference,
+        ])
+        .output()
+        .await
+        .map_err(|err| FlakeError::CantRunNix { err })?;
+    if !output.status.success() {
+        return Err(FlakeError::NixFailed {
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    };
+    let metadata: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|err| FlakeError::CantParseMetadata { err })?;
+    let missing = |field: &str| FlakeError::MissingMetadata {
+        field: field.to_string(),
+    };
+    let nar_hash = metadata
+        .get("locked")
+        .and_then(|locked| locked.get("narHash"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| missing("narHash"))?
+        .to_string();
+    let mut locked = FlakeReference::parse(
+        metadata
+            .get("url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| missing("url"))?,
+    )?;
+    locked.set_parameter("narHash", &nar_hash);
+    Ok(LockedFlake {
+        url: locked.to_string(),
+        nar_hash,
+    })
+}
+
+#[test]
+fn test_flake_reference() {
+    let reference = FlakeReference::parse("git+file:///repo?ref=main&dir=sub").unwrap();
+    assert_eq!(reference.scheme, "git+file");
+    assert_eq!(reference.local_path(), Some(PathBuf::from("/repo")));
+    assert_eq!(reference.parameter("dir"), Some("sub"));
+    assert_eq!(reference.to_string(), "git+file:///repo?ref=main&dir=sub");
+    let mut reference = FlakeReference::parse("path:/src?narHash=sha256-ab%2Bc%2F%3D").unwrap();
+    assert_eq!(reference.parameter("narHash"), Some("sha256-ab+c/="));
+    reference.set_parameter(
+        "narHash",
+        "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+    );
+    assert_eq!(
+        reference.to_string(),
+        "path:/src?narHash=sha256-47DEQpj8HBSa%2B/TImW%2B5JCeuQeRkm5NMpJWZG3hSuFU%3D"
+    );
+    assert_eq!(
+        FlakeReference::parse("github:NixOS/nixpkgs")
+            .unwrap()
+            .local_path(),
+        None
+    );
+    assert!(FlakeReference::parse("nixpkgs").is_err());
+}
+
+#[test]
+fn test_lock_local_flakes() {
+    let expected = TempDir::new("flake-expected").unwrap();
+    std::fs::write(expected.path().join("default.nix"), "{ }: 2").unwrap();
+    std::fs::write(expected.path().join("flake.nix"), "{ outputs = _: { }; }").unwrap();
+    std::fs::create_dir(expected.path().join("sub")).unwrap();
+    std::fs::write(
+        expected.path().join("sub/flake.nix"),
+        "{ outputs = _: { }; }",
+    )
+    .unwrap();
+    let expected_hash = to_sri_sha256(&nar_sha256(expected.path()).unwrap());
+    async_std::task::block_on(async {
+        let reference = format!("path:{}", expected.path().to_string_lossy());
+        let locked = lock_flake(&reference).await.unwrap();
+        assert_eq!(locked.nar_hash, expected_hash);
+        let mut expected_url = FlakeReference::parse(&reference).unwrap();
+        expected_url.set_parameter("narHash", &expected_hash);
+        assert_eq!(locked.url, expected_url.to_string());
+        assert_eq!(
+            FlakeReference::parse(&locked.url)
+                .unwrap()
+                .parameter("narHash"),
+            Some(expected_hash.as_str())
+        );
+        assert!(lock_flake(&format!("{}?dir=missing", reference))
+            .await
+            .is_err());
+        assert!(lock_flake("path:relative/flake").await.is_err());
+
+        let repository = crate::git::create_test_repository("flake");
+        let reference = format!("git+file://{}?ref=main", repository.to_string_lossy());
+        let locked = lock_flake(&reference).await.unwrap();
+        assert_eq!(locked.nar_hash, expected_hash);
+        let parsed = FlakeReference::parse(&locked.url).unwrap();
+        assert_eq!(parsed.parameter("ref"), Some("main"));
+        assert_eq!(parsed.parameter("rev").map(str::len), Some(40));
+        let in_dir = format!(
+            "git+file://{}?ref=main&dir=sub",
+            repository.to_string_lossy()
+        );
+        let locked = lock_flake(&in_dir).await.unwrap();
+        assert_eq!(locked.nar_hash, expected_hash);
+        let parsed = FlakeReference::parse(&locked.url).unwrap();
+        assert_eq!(parsed.parameter("dir"), Some("sub"));
+        let missing_dir = format!(
+            "git+file://{}?ref=main&dir=missing",
+            repository.to_string_lossy()
+        );
+        assert!(lock_flake(&missing_dir).await.is_err());
+        let tagged = format!("git+file://{}?ref=v1", repository.to_string_lossy());
+        assert_ne!(lock_flake(&tagged).await.unwrap().nar_hash, expected_hash);
+        std::fs::remove_dir_all(repository).unwrap();
+    });
+}
@@ -1,53 +1,486 @@
 use crate::config_manager::UserConfiguration;
-use serde::Deserialize;
+use crate::config_value::ConfigValue;
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 
+quick_error! {
+    #[derive(Debug)]
+    pub enum GateError {
+        MissingValue { keyword: String } {
+            display(me) -> ("the option {:?} used in a condition has no value", keyword)
+        }
+        MissingSourceOption { source: String, keyword: String } {
+            display(me) -> ("the option {:?} of the config source {:?} used in a condition has no value", keyword, source)
+        }
+        InvalidRegex { regex: String, err: regex::Error } {
+            cause(err)
+            display(me) -> ("the regex {:?} used in a condition is invalid: {}", regex, err)
+        }
+    }
+}
+
+/// The state of the other config sources, for the gates that depend on them
+pub trait SourcesState {
+    /// true if the config source with this id is installed and enabled
+    fn is_source_enabled(&self, id: &str) -> bool;
+    /// the value of an option of a config source, None if it isn't set
+    fn source_option(&self, id: &str, keyword: &str) -> Option<&ConfigValue>;
+}
+
+/// A `SourcesState` with no config source enabled, for evaluating gates out of any context
+pub struct NoSources;
+
+impl SourcesState for NoSources {
+    fn is_source_enabled(&self, _id: &str) -> bool {
+        false
+    }
+
+    fn source_option(&self, _id: &str, _keyword: &str) -> Option<&ConfigValue> {
+        None
+    }
+}
+
+quick_error! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum InvalidGate {
+        UnknownKeyword { path: String, keyword: String } {
+            display(me) -> ("{}: there is no option {:?}", path, keyword)
+        }
+        UnknownPossibility { path: String, keyword: String, value: String } {
+            display(me) -> ("{}: {:?} isn't a possibility of the option {:?}", path, value, keyword)
+        }
+        InvalidRegex { path: String, regex: String } {
+            display(me) -> ("{}: {:?} isn't a valid regex", path, regex)
+        }
+    }
+}
+
+/// The options a gate can refer to, with the possible values of the radio buttons (None for other
+/// kinds of option)
+pub type KnownOptions = BTreeMap<String, Option<Vec<String>>>;
+
+/// A floating point number, in a gate or an option. It is a separate type so they can still be
+/// hashed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(transparent)]
+pub struct Number(pub f64);
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+/// A condition on the options. In the config.json, it is either written in the tagged JSON form
+/// (`{"type": "KeywordTrue", "keyword": "stable"}`) or as a string expression (`"stable"`, see
+/// `gate_expression`).
 #[derive(Debug, Deserialize, Clone, Hash)]
-#[serde(tag = "type")]
+#[serde(tag = "type", remote = "Self")]
 pub enum Gate {
-    Not { gate: Box<Gate> },
-    And { gates: Vec<Gate> },
-    Or { gates: Vec<Gate> },
-    KeywordEqual { keyword: String, equal_to: String },
-    KeywordTrue { keyword: String },
-    KeywordFalse { keyword: String },
+    Not {
+        gate: Box<Gate>,
+    },
+    And {
+        gates: Vec<Gate>,
+    },
+    Or {
+        gates: Vec<Gate>,
+    },
+    KeywordEqual {
+        keyword: String,
+        equal_to: String,
+    },
+    KeywordTrue {
+        keyword: String,
+    },
+    KeywordFalse {
+        keyword: String,
+    },
+    /// true if the value of the option is one of `values`
+    KeywordIn {
+        keyword: String,
+        values: Vec<String>,
+    },
+    /// true if the regex match the value of the option. The regex isn't anchored, use `^` and `$`
+    /// to match the whole value.
+    KeywordMatches {
+        keyword: String,
+        regex: String,
+    },
+    /// true if the value of the option is a number lower than `than`. A value that isn't a number
+    /// is neither lower nor greater than anything.
+    KeywordLess {
+        keyword: String,
+        than: Number,
+    },
+    /// true if the value of the option is a number greater than `than`. A value that isn't a
+    /// number is neither lower nor greater than anything.
+    KeywordGreater {
+        keyword: String,
+        than: Number,
+    },
+    /// true if the option has no value, or an empty one
+    KeywordEmpty {
+        keyword: String,
+    },
+    /// true if the config source with this id is enabled
+    SourceEnabled {
+        id: String,
+    },
+    /// true if the config source is enabled, and its option `keyword` is equal to `equal_to`
+    SourceOption {
+        source: String,
+        keyword: String,
+        equal_to: String,
+    },
     True,
     False,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Gate {
     fn default() -> Self {
         Gate::True
     }
 }
 
+impl<'de> Deserialize<'de> for Gate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(expression) => expression.parse().map_err(|err| {
+                D::Error::custom(format!("invalid condition {:?}: {}", expression, err))
+            }),
+            value => Gate::deserialize(value).map_err(D::Error::custom),
+        }
+    }
+}
+
+fn get_value<'a>(
+    configuration: &'a UserConfiguration,
+    keyword: &str,
+) -> Result<&'a ConfigValue, GateError> {
+    configuration
+        .get(keyword)
+        .ok_or_else(|| GateError::MissingValue {
+            keyword: keyword.to_string(),
+        })
+}
+
+/// the value of the option as a number, None if it isn't one
+fn get_number(configuration: &UserConfiguration, keyword: &str) -> Result<Option<f64>, GateError> {
+    Ok(get_value(configuration, keyword)?.as_f64())
+}
+
 impl Gate {
-    pub fn evaluate(&self, configuration: &UserConfiguration) -> bool {
-        //TODO: consider returning a result instead
-        match self {
-            Self::Not { gate } => !gate.evaluate(configuration),
+    /// evaluate the gate, with `configuration` the options of its config source and `sources`
+    /// the state of the other config sources
+    pub fn evaluate(
+        &self,
+        configuration: &UserConfiguration,
+        sources: &dyn SourcesState,
+    ) -> Result<bool, GateError> {
+        Ok(match self {
+            Self::Not { gate } => !gate.evaluate(configuration, sources)?,
             Self::And { gates } => {
                 for gate in gates {
-                    if !gate.evaluate(configuration) {
-                        return false;
+                    if !gate.evaluate(configuration, sources)? {
+                        return Ok(false);
                     };
                 }
                 true
             }
             Self::Or { gates } => {
                 for gate in gates {
-                    if gate.evaluate(configuration) {
-                        return true;
+                    if gate.evaluate(configuration, sources)? {
+                        return Ok(true);
                     };
                 }
                 false
             }
             Self::KeywordEqual { keyword, equal_to } => {
-                configuration.get(keyword).unwrap() == equal_to
+                get_value(configuration, keyword)?.to_string() == *equal_to
+            }
+            Self::KeywordTrue { keyword } => get_value(configuration, keyword)?.is_true(),
+            Self::KeywordFalse { keyword } => !get_value(configuration, keyword)?.is_true(),
+            Self::KeywordIn { keyword, values } => {
+                values.contains(&get_value(configuration, keyword)?.to_string())
+            }
+            Self::KeywordMatches { keyword, regex } => {
+                let value = get_value(configuration, keyword)?;
+                Regex::new(regex)
+                    .map_err(|err| GateError::InvalidRegex {
+                        regex: regex.clone(),
+                        err,
+                    })?
+                    .is_match(&value.to_string())
+            }
+            Self::KeywordLess { keyword, than } => {
+                matches!(get_number(configuration, keyword)?, Some(value) if value < than.0)
+            }
+            Self::KeywordGreater { keyword, than } => {
+                matches!(get_number(configuration, keyword)?, Some(value) if value > than.0)
+            }
+            Self::KeywordEmpty { keyword } => configuration
+                .get(keyword)
+                .map(ConfigValue::is_empty)
+                .unwrap_or(true),
+            Self::SourceEnabled { id } => sources.is_source_enabled(id),
+            Self::SourceOption {
+                source,
+                keyword,
+                equal_to,
+            } => {
+                if !sources.is_source_enabled(source) {
+                    return Ok(false);
+                };
+                sources
+                    .source_option(source, keyword)
+                    .ok_or_else(|| GateError::MissingSourceOption {
+                        source: source.clone(),
+                        keyword: keyword.clone(),
+                    })?
+                    .to_string()
+                    == *equal_to
             }
-            Self::KeywordTrue { keyword } => configuration.get(keyword).unwrap() == "true",
-            Self::KeywordFalse { keyword } => configuration.get(keyword).unwrap() != "true",
             Self::True => true,
             Self::False => false,
+        })
+    }
+
+    /// check that this gate only refer to existing options, and to existing possibilities of the
+    /// radio buttons. `path` is the JSON path of this gate, used in the reported problems.
+    pub fn validate(&self, path: &str, options: &KnownOptions, problems: &mut Vec<InvalidGate>) {
+        let check_keyword = |keyword: &str, problems: &mut Vec<InvalidGate>| {
+            let known = options.contains_key(keyword);
+            if !known {
+                problems.push(InvalidGate::UnknownKeyword {
+                    path: path.to_string(),
+                    keyword: keyword.to_string(),
+                });
+            };
+            known
+        };
+        let check_possibilities =
+            |keyword: &str, values: &[String], problems: &mut Vec<InvalidGate>| {
+                if let Some(Some(possibilities)) = options.get(keyword) {
+                    for value in values {
+                        if !possibilities.contains(value) {
+                            problems.push(InvalidGate::UnknownPossibility {
+                                path: path.to_string(),
+                                keyword: keyword.to_string(),
+                                value: value.clone(),
+                            });
+                        }
+                    }
+                }
+            };
+        match self {
+            Self::Not { gate } => gate.validate(&format!("{}.gate", path), options, problems),
+            Self::And { gates } | Self::Or { gates } => {
+                for (count, gate) in gates.iter().enumerate() {
+                    gate.validate(&format!("{}.gates[{}]", path, count), options, problems);
+                }
+            }
+            Self::KeywordEqual { keyword, equal_to } => {
+                if check_keyword(keyword, problems) {
+                    check_possibilities(keyword, std::slice::from_ref(equal_to), problems);
+                }
+            }
+            Self::KeywordIn { keyword, values } => {
+                if check_keyword(keyword, problems) {
+                    check_possibilities(keyword, values, problems);
+                }
+            }
+            Self::KeywordMatches { keyword, regex } => {
+                check_keyword(keyword, problems);
+                if Regex::new(regex).is_err() {
+                    problems.push(InvalidGate::InvalidRegex {
+                        path: path.to_string(),
+                        regex: regex.clone(),
+                    });
+                }
+            }
+            Self::KeywordTrue { keyword }
+            | Self::KeywordFalse { keyword }
+            | Self::KeywordLess { keyword, .. }
+            | Self::KeywordGreater { keyword, .. }
+            | Self::KeywordEmpty { keyword } => {
+                check_keyword(keyword, problems);
+            }
+            // the other config sources may not be loaded yet, so they can't be checked here
+            Self::SourceEnabled { .. } | Self::SourceOption { .. } | Self::True | Self::False => (),
         }
     }
 }
+
+#[test]
+fn test_gate() {
+    let gate: Gate = serde_json::from_str(
+        r#"{"type": "And", "gates": [
+            {"type": "KeywordTrue", "keyword": "stable"},
+            {"type": "Not", "gate": {"type": "KeywordEqual", "keyword": "release", "equal_to": "beta"}}
+        ]}"#,
+    )
+    .unwrap();
+    let mut configuration = UserConfiguration::new();
+    configuration.insert("stable".to_string(), true.into());
+    assert!(matches!(
+        gate.evaluate(&configuration, &NoSources),
+        Err(GateError::MissingValue { .. })
+    ));
+    configuration.insert("release".to_string(), "alpha".into());
+    assert!(gate.evaluate(&configuration, &NoSources).unwrap());
+
+    let mut options = KnownOptions::new();
+    options.insert("stable".to_string(), None);
+    let mut problems = Vec::new();
+    gate.validate("configurations[0].condition", &options, &mut problems);
+    assert_eq!(
+        problems,
+        vec![InvalidGate::UnknownKeyword {
+            path: "configurations[0].condition.gates[1].gate".to_string(),
+            keyword: "release".to_string()
+        }]
+    );
+    options.insert(
+        "release".to_string(),
+        Some(vec!["alpha".to_string(), "demo".to_string()]),
+    );
+    problems.clear();
+    gate.validate("condition", &options, &mut problems);
+    assert!(matches!(
+        &problems[..],
+        [InvalidGate::UnknownPossibility { value, .. }] if value == "beta"
+    ));
+}
+
+#[test]
+fn test_source_gates() {
+    struct ModManager(UserConfiguration);
+    impl SourcesState for ModManager {
+        fn is_source_enabled(&self, id: &str) -> bool {
+            id == "mod-manager"
+        }
+        fn source_option(&self, id: &str, keyword: &str) -> Option<&ConfigValue> {
+            if id == "mod-manager" {
+                self.0.get(keyword)
+            } else {
+                None
+            }
+        }
+    }
+    let mut options = UserConfiguration::new();
+    options.insert("portal".to_string(), true.into());
+    let sources = ModManager(options);
+    let local = UserConfiguration::new();
+    let enabled = |id: &str| Gate::SourceEnabled { id: id.to_string() };
+    assert!(enabled("mod-manager").evaluate(&local, &sources).unwrap());
+    assert!(!enabled("other").evaluate(&local, &sources).unwrap());
+    assert!(!enabled("mod-manager").evaluate(&local, &NoSources).unwrap());
+    let option = |source: &str, keyword: &str| Gate::SourceOption {
+        source: source.to_string(),
+        keyword: keyword.to_string(),
+        equal_to: "true".to_string(),
+    };
+    assert!(option("mod-manager", "portal")
+        .evaluate(&local, &sources)
+        .unwrap());
+    assert!(!option("other", "portal")
+        .evaluate(&local, &sources)
+        .unwrap());
+    assert!(option("mod-manager", "missing")
+        .evaluate(&local, &sources)
+        .is_err());
+}
+
+#[test]
+fn test_comparison_gates() {
+    let gate = |json: &str| -> Gate { serde_json::from_str(json).unwrap() };
+    let mut configuration = UserConfiguration::new();
+    configuration.insert("release".to_string(), "beta".into());
+    configuration.insert("players".to_string(), ConfigValue::Integer(8));
+    configuration.insert("name".to_string(), "my server".into());
+    configuration.insert("motd".to_string(), "".into());
+    let evaluate = |gate: &Gate| gate.evaluate(&configuration, &NoSources);
+
+    let in_gate =
+        gate(r#"{"type": "KeywordIn", "keyword": "release", "values": ["alpha", "beta"]}"#);
+    assert!(evaluate(&in_gate).unwrap());
+    assert!(!evaluate(&gate(
+        r#"{"type": "KeywordIn", "keyword": "release", "values": []}"#
+    ))
+    .unwrap());
+    assert!(matches!(
+        evaluate(&gate(
+            r#"{"type": "KeywordIn", "keyword": "missing", "values": ["a"]}"#
+        )),
+        Err(GateError::MissingValue { .. })
+    ));
+
+    assert!(evaluate(&gate(
+        r#"{"type": "KeywordMatches", "keyword": "name", "regex": "^my "}"#
+    ))
+    .unwrap());
+    assert!(!evaluate(&gate(
+        r#"{"type": "KeywordMatches", "keyword": "name", "regex": "^server"}"#
+    ))
+    .unwrap());
+    let invalid_regex = gate(r#"{"type": "KeywordMatches", "keyword": "name", "regex": "("}"#);
+    assert!(matches!(
+        evaluate(&invalid_regex),
+        Err(GateError::InvalidRegex { .. })
+    ));
+
+    assert!(evaluate(&gate(
+        r#"{"type": "KeywordLess", "keyword": "players", "than": 10}"#
+    ))
+    .unwrap());
+    assert!(!evaluate(&gate(
+        r#"{"type": "KeywordLess", "keyword": "players", "than": 8}"#
+    ))
+    .unwrap());
+    assert!(evaluate(&gate(
+        r#"{"type": "KeywordGreater", "keyword": "players", "than": 7.5}"#
+    ))
+    .unwrap());
+    // a value that isn't a number is neither lower nor greater
+    assert!(!evaluate(&gate(
+        r#"{"type": "KeywordLess", "keyword": "name", "than": 10}"#
+    ))
+    .unwrap());
+    assert!(!evaluate(&gate(
+        r#"{"type": "KeywordGreater", "keyword": "name", "than": 10}"#
+    ))
+    .unwrap());
+    assert!(matches!(
+        evaluate(&gate(
+            r#"{"type": "KeywordGreater", "keyword": "missing", "than": 1}"#
+        )),
+        Err(GateError::MissingValue { .. })
+    ));
+
+    assert!(evaluate(&gate(r#"{"type": "KeywordEmpty", "keyword": "motd"}"#)).unwrap());
+    assert!(evaluate(&gate(r#"{"type": "KeywordEmpty", "keyword": "missing"}"#)).unwrap());
+    assert!(!evaluate(&gate(r#"{"type": "KeywordEmpty", "keyword": "name"}"#)).unwrap());
+
+    let mut options = KnownOptions::new();
+    options.insert(
+        "release".to_string(),
+        Some(vec!["alpha".to_string(), "stable".to_string()]),
+    );
+    options.insert("name".to_string(), None);
+    let mut problems = Vec::new();
+    in_gate.validate("condition", &options, &mut problems);
+    invalid_regex.validate("condition", &options, &mut problems);
+    assert!(matches!(
+        &problems[..],
+        [
+            InvalidGate::UnknownPossibility { value, .. },
+            InvalidGate::InvalidRegex { .. }
+        ] if value == "beta"
+    ));
+}
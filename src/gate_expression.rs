@@ -0,0 +1,647 @@
+//! A human writable syntax for gates, like `releaseType != 'demo' && stable`. It can be used in
+//! place of the tagged JSON form in the config.json.
+//!
+//! - `keyword` is true if the option is true, `!keyword` if it isn't
+//! - `keyword == 'value'` and `keyword != 'value'` compare the value of an option
+//! - `keyword in ['a', 'b']`, `keyword =~ 'regex'`, `keyword < 3` and `keyword > 3`
+//! - `empty(keyword)`, `enabled('source')` and `option('source', 'keyword') == 'value'`
+//! - `true`, `false`, `!`, `&&`, `||` and parentheses, with `&&` taking precedence over `||`
+//!
+//! Keywords that aren't plain names (like `` `max players` `` or `` `true` ``) are written between
+//! backticks.
+use crate::gate::{Gate, Number};
+use std::fmt;
+use std::str::FromStr;
+
+quick_error! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum GateSyntaxError {
+        Unexpected { column: usize, expected: String, found: String } {
+            display(me) -> ("column {}: expected {}, found {}", column, expected, found)
+        }
+        UnterminatedString { column: usize } {
+            display(me) -> ("column {}: the string isn't terminated", column)
+        }
+        InvalidNumber { column: usize, number: String } {
+            display(me) -> ("column {}: {:?} isn't a valid number", column, number)
+        }
+        UnknownFunction { column: usize, name: String } {
+            display(me) -> ("column {}: unknown function {:?} (expected empty, enabled or option)", column, name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    /// a keyword written between backticks
+    QuotedKeyword(String),
+    String(String),
+    Number(f64),
+    OpenParenthesis,
+    CloseParenthesis,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Not,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Matches,
+    Less,
+    Greater,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Identifier(identifier) => write!(f, "{:?}", identifier),
+            Self::QuotedKeyword(keyword) => write!(f, "the keyword {}", quote_keyword(keyword)),
+            Self::String(string) => write!(f, "the string {}", quote(string)),
+            Self::Number(number) => write!(f, "the number {}", number),
+            Self::OpenParenthesis => write!(f, "'('"),
+            Self::CloseParenthesis => write!(f, "')'"),
+            Self::OpenBracket => write!(f, "'['"),
+            Self::CloseBracket => write!(f, "']'"),
+            Self::Comma => write!(f, "','"),
+            Self::Not => write!(f, "'!'"),
+            Self::And => write!(f, "'&&'"),
+            Self::Or => write!(f, "'||'"),
+            Self::Equal => write!(f, "'=='"),
+            Self::NotEqual => write!(f, "'!='"),
+            Self::Matches => write!(f, "'=~'"),
+            Self::Less => write!(f, "'<'"),
+            Self::Greater => write!(f, "'>'"),
+            Self::End => write!(f, "the end of the condition"),
+        }
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// split the expression in tokens, with their (1 based) column
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, GateSyntaxError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        let column = position + 1;
+        let c = chars[position];
+        let next = chars.get(position + 1).copied();
+        let (token, length) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                position += 1;
+                continue;
+            }
+            ('(', _) => (Token::OpenParenthesis, 1),
+            (')', _) => (Token::CloseParenthesis, 1),
+            ('[', _) => (Token::OpenBracket, 1),
+            (']', _) => (Token::CloseBracket, 1),
+            (',', _) => (Token::Comma, 1),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Equal, 2),
+            ('=', Some('~')) => (Token::Matches, 2),
+            ('!', Some('=')) => (Token::NotEqual, 2),
+            ('!', _) => (Token::Not, 1),
+            ('<', _) => (Token::Less, 1),
+            ('>', _) => (Token::Greater, 1),
+            ('\'', _) | ('"', _) | ('`', _) => {
+                let mut string = String::new();
+                let mut end = position + 1;
+                loop {
+                    match chars.get(end) {
+                        None => return Err(GateSyntaxError::UnterminatedString { column }),
+                        Some(&quote) if quote == c => break,
+                        Some('\\') => {
+                            let escaped = chars
+                                .get(end + 1)
+                                .ok_or(GateSyntaxError::UnterminatedString { column })?;
+                            string.push(*escaped);
+                            end += 2;
+                        }
+                        Some(other) => {
+                            string.push(*other);
+                            end += 1;
+                        }
+                    }
+                }
+                let token = if c == '`' {
+                    Token::QuotedKeyword(string)
+                } else {
+                    Token::String(string)
+                };
+                (token, end + 1 - position)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut end = position + 1;
+                while let Some(&c) = chars.get(end) {
+                    let after_exponent = matches!(chars[end - 1], 'e' | 'E');
+                    if c.is_ascii_digit()
+                        || matches!(c, '.' | 'e' | 'E')
+                        || (after_exponent && matches!(c, '+' | '-'))
+                    {
+                        end += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let number: String = chars[position..end].iter().collect();
+                match number.parse::<f64>() {
+                    Ok(value) if value.is_finite() => (Token::Number(value), end - position),
+                    _ => return Err(GateSyntaxError::InvalidNumber { column, number }),
+                }
+            }
+            (c, _) if is_identifier_start(c) => {
+                let mut end = position + 1;
+                while chars.get(end).copied().is_some_and(is_identifier_char) {
+                    end += 1;
+                }
+                let identifier = chars[position..end].iter().collect();
+                (Token::Identifier(identifier), end - position)
+            }
+            (c, _) => {
+                return Err(GateSyntaxError::Unexpected {
+                    column,
+                    expected: "a condition".to_string(),
+                    found: format!("{:?}", c),
+                })
+            }
+        };
+        tokens.push((token, column));
+        position += length;
+    }
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.position].clone();
+        if token.0 != Token::End {
+            self.position += 1;
+        };
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> GateSyntaxError {
+        let (found, column) = &self.tokens[self.position];
+        GateSyntaxError::Unexpected {
+            column: *column,
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), GateSyntaxError> {
+        if *self.peek() == token {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, GateSyntaxError> {
+        match self.peek().clone() {
+            Token::String(string) => {
+                self.next();
+                Ok(string)
+            }
+            _ => Err(self.unexpected("a quoted string")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Number, GateSyntaxError> {
+        match *self.peek() {
+            Token::Number(number) => {
+                self.next();
+                Ok(Number(number))
+            }
+            _ => Err(self.unexpected("a number")),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, GateSyntaxError> {
+        match self.peek().clone() {
+            Token::Identifier(identifier) | Token::QuotedKeyword(identifier) => {
+                self.next();
+                Ok(identifier)
+            }
+            _ => Err(self.unexpected("the name of an option")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Gate, GateSyntaxError> {
+        let mut gates = vec![self.and()?];
+        while *self.peek() == Token::Or {
+            self.next();
+            gates.push(self.and()?);
+        }
+        Ok(if gates.len() == 1 {
+            gates.remove(0)
+        } else {
+            Gate::Or { gates }
+        })
+    }
+
+    fn and(&mut self) -> Result<Gate, GateSyntaxError> {
+        let mut gates = vec![self.unary()?];
+        while *self.peek() == Token::And {
+            self.next();
+            gates.push(self.unary()?);
+        }
+        Ok(if gates.len() == 1 {
+            gates.remove(0)
+        } else {
+            Gate::And { gates }
+        })
+    }
+
+    fn unary(&mut self) -> Result<Gate, GateSyntaxError> {
+        if *self.peek() == Token::Not {
+            self.next();
+            Ok(match self.unary()? {
+                Gate::KeywordTrue { keyword } => Gate::KeywordFalse { keyword },
+                gate => Gate::Not {
+                    gate: Box::new(gate),
+                },
+            })
+        } else {
+            self.primary()
+        }
+    }
+
+    /// parse `== 'value'` or `!= 'value'` after `left`
+    fn equality(&mut self, left: Gate) -> Result<Gate, GateSyntaxError> {
+        let negated = match self.peek() {
+            Token::Equal => false,
+            Token::NotEqual => true,
+            _ => return Err(self.unexpected("'==' or '!='")),
+        };
+        self.next();
+        let value = self.string()?;
+        let gate = match left {
+            Gate::SourceOption {
+                source, keyword, ..
+            } => Gate::SourceOption {
+                source,
+                keyword,
+                equal_to: value,
+            },
+            Gate::KeywordEqual { keyword, .. } => Gate::KeywordEqual {
+                keyword,
+                equal_to: value,
+            },
+            _ => unreachable!(),
+        };
+        Ok(if negated {
+            Gate::Not {
+                gate: Box::new(gate),
+            }
+        } else {
+            gate
+        })
+    }
+
+    fn primary(&mut self) -> Result<Gate, GateSyntaxError> {
+        let column = self.tokens[self.position].1;
+        match self.peek().clone() {
+            Token::OpenParenthesis => {
+                self.next();
+                let gate = self.or()?;
+                self.expect(Token::CloseParenthesis)?;
+                Ok(gate)
+            }
+            Token::QuotedKeyword(keyword) => {
+                self.next();
+                self.keyword_gate(keyword)
+            }
+            Token::Identifier(identifier) => {
+                self.next();
+                if *self.peek() == Token::OpenParenthesis {
+                    self.next();
+                    let gate = match identifier.as_str() {
+                        "empty" => Gate::KeywordEmpty {
+                            keyword: self.identifier()?,
+                        },
+                        "enabled" => Gate::SourceEnabled { id: self.string()? },
+                        "option" => {
+                            let source = self.string()?;
+                            self.expect(Token::Comma)?;
+                            let keyword = self.string()?;
+                            self.expect(Token::CloseParenthesis)?;
+                            return self.equality(Gate::SourceOption {
+                                source,
+                                keyword,
+                                equal_to: String::new(),
+                            });
+                        }
+                        _ => {
+                            return Err(GateSyntaxError::UnknownFunction {
+                                column,
+                                name: identifier,
+                            })
+                        }
+                    };
+                    self.expect(Token::CloseParenthesis)?;
+                    return Ok(gate);
+                };
+                match identifier.as_str() {
+                    "true" => Ok(Gate::True),
+                    "false" => Ok(Gate::False),
+                    _ => self.keyword_gate(identifier),
+                }
+            }
+            _ => Err(self.unexpected("a condition")),
+        }
+    }
+
+    /// parse what follow a keyword: a comparison, or nothing if the keyword should be true
+    fn keyword_gate(&mut self, keyword: String) -> Result<Gate, GateSyntaxError> {
+        Ok(match self.peek() {
+            Token::Equal | Token::NotEqual => self.equality(Gate::KeywordEqual {
+                keyword,
+                equal_to: String::new(),
+            })?,
+            Token::Matches => {
+                self.next();
+                Gate::KeywordMatches {
+                    keyword,
+                    regex: self.string()?,
+                }
+            }
+            Token::Less => {
+                self.next();
+                Gate::KeywordLess {
+                    keyword,
+                    than: self.number()?,
+                }
+            }
+            Token::Greater => {
+                self.next();
+                Gate::KeywordGreater {
+                    keyword,
+                    than: self.number()?,
+                }
+            }
+            Token::Identifier(operator) if operator == "in" => {
+                self.next();
+                self.expect(Token::OpenBracket)?;
+                let mut values = Vec::new();
+                while *self.peek() != Token::CloseBracket {
+                    values.push(self.string()?);
+                    if *self.peek() != Token::CloseBracket {
+                        self.expect(Token::Comma)?;
+                    };
+                }
+                self.next();
+                Gate::KeywordIn { keyword, values }
+            }
+            _ => Gate::KeywordTrue { keyword },
+        })
+    }
+}
+
+impl FromStr for Gate {
+    type Err = GateSyntaxError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+        };
+        let gate = parser.or()?;
+        if *parser.peek() != Token::End {
+            return Err(parser.unexpected("'&&', '||' or the end of the condition"));
+        };
+        Ok(gate)
+    }
+}
+
+/// quote a string, so it is parsed back to the same string
+fn quote(string: &str) -> String {
+    format!("'{}'", string.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// names that have a meaning by themselves in an expression, and are quoted when used as keywords
+const RESERVED_KEYWORDS: &[&str] = &["true", "false", "in", "empty", "enabled", "option"];
+
+/// write a keyword as is if it is a plain name, or between backticks otherwise
+fn quote_keyword(keyword: &str) -> String {
+    let plain = keyword.starts_with(is_identifier_start)
+        && keyword.chars().all(is_identifier_char)
+        && !RESERVED_KEYWORDS.contains(&keyword);
+    if plain {
+        keyword.to_string()
+    } else {
+        format!("`{}`", keyword.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+impl Gate {
+    /// write the gate, with parenthesis if its precedence is lower than `precedence` (0 for `||`, 1
+    /// for `&&`, 2 for the other gates)
+    fn write_expression(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, gates: &[Gate], operator, own_precedence| {
+            if own_precedence < precedence {
+                write!(f, "(")?;
+            };
+            for (count, gate) in gates.iter().enumerate() {
+                if count != 0 {
+                    write!(f, " {} ", operator)?;
+                };
+                gate.write_expression(f, own_precedence + 1)?;
+            }
+            if own_precedence < precedence {
+                write!(f, ")")?;
+            };
+            Ok(())
+        };
+        match self {
+            Self::Or { gates } if gates.is_empty() => write!(f, "false"),
+            Self::And { gates } if gates.is_empty() => write!(f, "true"),
+            Self::Or { gates } | Self::And { gates } if gates.len() == 1 => {
+                gates[0].write_expression(f, precedence)
+            }
+            Self::Or { gates } => join(f, gates, "||", 0),
+            Self::And { gates } => join(f, gates, "&&", 1),
+            Self::Not { gate } => match gate.as_ref() {
+                Self::KeywordEqual { keyword, equal_to } => {
+                    write!(f, "{} != {}", quote_keyword(keyword), quote(equal_to))
+                }
+                Self::SourceOption {
+                    source,
+                    keyword,
+                    equal_to,
+                } => write!(
+                    f,
+                    "option({}, {}) != {}",
+                    quote(source),
+                    quote(keyword),
+                    quote(equal_to)
+                ),
+                Self::True
+                | Self::False
+                | Self::KeywordTrue { .. }
+                | Self::KeywordEmpty { .. }
+                | Self::SourceEnabled { .. } => write!(f, "!{}", gate),
+                gate => write!(f, "!({})", gate),
+            },
+            Self::KeywordEqual { keyword, equal_to } => {
+                write!(f, "{} == {}", quote_keyword(keyword), quote(equal_to))
+            }
+            Self::KeywordTrue { keyword } => write!(f, "{}", quote_keyword(keyword)),
+            Self::KeywordFalse { keyword } => write!(f, "!{}", quote_keyword(keyword)),
+            Self::KeywordIn { keyword, values } => write!(
+                f,
+                "{} in [{}]",
+                quote_keyword(keyword),
+                values
+                    .iter()
+                    .map(|value| quote(value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::KeywordMatches { keyword, regex } => {
+                write!(f, "{} =~ {}", quote_keyword(keyword), quote(regex))
+            }
+            Self::KeywordLess { keyword, than } => {
+                write!(f, "{} < {}", quote_keyword(keyword), than.0)
+            }
+            Self::KeywordGreater { keyword, than } => {
+                write!(f, "{} > {}", quote_keyword(keyword), than.0)
+            }
+            Self::KeywordEmpty { keyword } => write!(f, "empty({})", quote_keyword(keyword)),
+            Self::SourceEnabled { id } => write!(f, "enabled({})", quote(id)),
+            Self::SourceOption {
+                source,
+                keyword,
+                equal_to,
+            } => write!(
+                f,
+                "option({}, {}) == {}",
+                quote(source),
+                quote(keyword),
+                quote(equal_to)
+            ),
+            Self::True => write!(f, "true"),
+            Self::False => write!(f, "false"),
+        }
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_expression(f, 0)
+    }
+}
+
+#[test]
+fn test_parse_gate_expression() {
+    let gate: Gate = "releaseType != 'demo' && stable".parse().unwrap();
+    assert!(matches!(
+        &gate,
+        Gate::And { gates } if matches!(
+            &gates[..],
+            [Gate::Not { gate }, Gate::KeywordTrue { keyword }]
+                if keyword == "stable"
+                && matches!(gate.as_ref(), Gate::KeywordEqual { equal_to, .. } if equal_to == "demo")
+        )
+    ));
+
+    for expression in &[
+        "releaseType != 'demo' && stable",
+        "a || b && !c",
+        "(a || b) && !(c || d)",
+        "release in ['alpha', 'it\\'s'] || name =~ '^my\\\\d' || players < 8 || players > -1.5",
+        "!empty(token) && enabled('mod-manager') && option('mod-manager', 'portal') != 'true'",
+        "true && !false",
+        "`true` && !`false` && `in` == 'x' && `max players` > 2 && !empty(`a.b`) && `3d`",
+        "`back\\`tick` in ['a'] || `back\\\\slash` =~ 'b' || `option` < 1",
+    ] {
+        let gate: Gate = expression.parse().unwrap();
+        assert_eq!(&gate.to_string(), expression);
+    }
+
+    // keywords that aren't plain names are quoted, so they are parsed back to the same gate
+    for keyword in &[
+        "true",
+        "false",
+        "in",
+        "empty",
+        "max players",
+        "a.b",
+        "3d",
+        "`",
+        "\\",
+    ] {
+        for gate in &[
+            Gate::KeywordTrue {
+                keyword: keyword.to_string(),
+            },
+            Gate::KeywordFalse {
+                keyword: keyword.to_string(),
+            },
+            Gate::KeywordEmpty {
+                keyword: keyword.to_string(),
+            },
+            Gate::KeywordLess {
+                keyword: keyword.to_string(),
+                than: Number(3.0),
+            },
+        ] {
+            let parsed: Gate = gate.to_string().parse().unwrap();
+            assert_eq!(format!("{:?}", parsed), format!("{:?}", gate));
+        }
+    }
+
+    // the tagged JSON form is rendered too
+    let gate: Gate = serde_json::from_str(
+        r#"{"type": "Or", "gates": [
+            {"type": "And", "gates": [{"type": "KeywordTrue", "keyword": "a"}, "b || c"]},
+            {"type": "KeywordFalse", "keyword": "d"}
+        ]}"#,
+    )
+    .unwrap();
+    assert_eq!(gate.to_string(), "a && (b || c) || !d");
+
+    assert_eq!(
+        "stable &&".parse::<Gate>().unwrap_err(),
+        GateSyntaxError::Unexpected {
+            column: 10,
+            expected: "a condition".to_string(),
+            found: "the end of the condition".to_string()
+        }
+    );
+    assert_eq!(
+        "a == 'b".parse::<Gate>().unwrap_err(),
+        GateSyntaxError::UnterminatedString { column: 6 }
+    );
+    assert_eq!(
+        "a < 1.2.3".parse::<Gate>().unwrap_err().to_string(),
+        "column 5: \"1.2.3\" isn't a valid number"
+    );
+    assert!(matches!(
+        "a b".parse::<Gate>().unwrap_err(),
+        GateSyntaxError::Unexpected { column: 3, .. }
+    ));
+    assert!(serde_json::from_str::<Gate>(r#""a == ""#).is_err());
+}
@@ -0,0 +1,177 @@
+use crate::temp_dir::TempDir;
+use async_std::process::Command;
+use std::io;
+use std::path::Path;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum GitError {
+        CantRunGit { err: io::Error } {
+            cause(err)
+            display(me) -> ("can't run git: {}", err)
+        }
+        CommandFailed { args: Vec<String>, stderr: String } {
+            display(me) -> ("git {} failed: {}", args.join(" "), stderr.trim())
+        }
+        CantCreateTempDir { err: io::Error } {
+            cause(err)
+            display(me) -> ("can't create a temporary directory: {}", err)
+        }
+        InvalidReference { reference: String } {
+            display(me) -> ("{:?} isn't a valid reference, it can't start with \"-\"", reference)
+        }
+    }
+}
+
+/// A revision of a git repository, as resolved by [`resolve_reference`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRevision {
+    /// the commit hash
+    pub rev: String,
+    /// the hash of the tree object of this commit
+    pub tree: String,
+}
+
+/// A bare repository in the temporary directory, deleted when dropped
+pub struct TempRepository {
+    directory: TempDir,
+}
+
+impl TempRepository {
+    pub async fn new() -> Result<Self, GitError> {
+        let repository = Self {
+            directory: TempDir::new("git").map_err(|err| GitError::CantCreateTempDir { err })?,
+        };
+        run_git(repository.path(), &["init", "--bare", "--quiet"]).await?;
+        Ok(repository)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.directory.path()
+    }
+}
+
+pub async fn run_git(directory: &Path, args: &[&str]) -> Result<String, GitError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .await
+        .map_err(|err| GitError::CantRunGit { err })?;
+    if !output.status.success() {
+        return Err(GitError::CommandFailed {
+            args: args.iter().map(|x| x.to_string()).collect(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    };
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Fetch the given reference (a branch, a tag, or a commit hash) of the repository at `url` in
+/// `repository`, and return the commit it point to.
+pub async fn fetch_reference(
+    repository: &TempRepository,
+    url: &str,
+    reference: &str,
+) -> Result<ResolvedRevision, GitError> {
+    // a reference starting with "-" would be an option, even after "--"
+    if reference.starts_with('-') {
+        return Err(GitError::InvalidReference {
+            reference: reference.to_string(),
+        });
+    };
+    run_git(
+        repository.path(),
+        &["fetch", "--quiet", "--depth=1", "--", url, reference],
+    )
+    .await?;
+    Ok(ResolvedRevision {
+        rev: run_git(repository.path(), &["rev-parse", "FETCH_HEAD^{commit}"]).await?,
+        tree: run_git(repository.path(), &["rev-parse", "FETCH_HEAD^{tree}"]).await?,
+    })
+}
+
+/// Write the files of the given commit of `repository` to `destination`, without the `.git`
+/// folder.
+pub async fn checkout_tree(
+    repository: &TempRepository,
+    rev: &str,
+    destination: &Path,
+) -> Result<(), GitError> {
+    let work_tree = destination.to_string_lossy().to_string();
+    run_git(
+        repository.path(),
+        &[
+            "--work-tree",
+            &work_tree,
+            "checkout",
+            "--quiet",
+            rev,
+            "--",
+            ".",
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Resolve a branch, a tag or a commit hash of the repository at `url` to a commit.
+pub async fn resolve_reference(url: &str, reference: &str) -> Result<ResolvedRevision, GitError> {
+    let repository = TempRepository::new().await?;
+    fetch_reference(&repository, url, reference).await
+}
+
+#[cfg(test)]
+pub fn create_test_repository(name: &str) -> std::path::PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("nixminigui-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&path)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(status.status.success(), "{:?}", status);
+    };
+    git(&["init", "--quiet", "--initial-branch=main"]);
+    std::fs::write(path.join("default.nix"), "{ }: 1").unwrap();
+    std::fs::write(path.join("flake.nix"), "{ outputs = _: { }; }").unwrap();
+    std::fs::create_dir(path.join("sub")).unwrap();
+    std::fs::write(path.join("sub/flake.nix"), "{ outputs = _: { }; }").unwrap();
+    git(&["add", "default.nix", "flake.nix", "sub/flake.nix"]);
+    git(&["commit", "--quiet", "-m", "first"]);
+    git(&["tag", "v1"]);
+    std::fs::write(path.join("default.nix"), "{ }: 2").unwrap();
+    git(&["commit", "--quiet", "-am", "second"]);
+    path
+}
+
+#[test]
+fn test_resolve_reference() {
+    let repository = create_test_repository("resolve-reference");
+    let url = format!("file://{}", repository.to_string_lossy());
+    async_std::task::block_on(async {
+        let main = resolve_reference(&url, "main").await.unwrap();
+        let tag = resolve_reference(&url, "v1").await.unwrap();
+        assert_ne!(main.rev, tag.rev);
+        assert_ne!(main.tree, tag.tree);
+        assert_eq!(main.rev.len(), 40);
+        assert_eq!(resolve_reference(&url, &tag.rev).await.unwrap(), tag);
+        assert!(resolve_reference(&url, "missing-branch").await.is_err());
+        // an url or a reference starting with "-" isn't read as an option of git
+        assert!(matches!(
+            resolve_reference(&url, "--upload-pack=touch /tmp/pwned").await,
+            Err(GitError::InvalidReference { .. })
+        ));
+        assert!(matches!(
+            resolve_reference("--upload-pack=touch /tmp/pwned", "main").await,
+            Err(GitError::CommandFailed { .. })
+        ));
+    });
+    std::fs::remove_dir_all(repository).unwrap();
+}
@@ -1,7 +1,11 @@
 use crate::config_manager::ConfigManager;
 use crate::config_source::ConfigSource;
+use crate::gate::SourcesState;
 use crate::gui::DisplayedConfiguration;
+use crate::input_graph::GraphFormat;
+use crate::lock_diff::{LockUpdateReview, PinChange};
 use crate::ongoing_save::OngoingSaveProgressMessage;
+use std::collections::BTreeMap;
 
 use iced::Container;
 use iced::Length;
@@ -9,17 +13,52 @@ use iced::Length;
 use crate::gui::{AppSetting, Message};
 use iced::Subscription;
 use iced::{
-    button, executor, scrollable, Application, Button, Column, Command, Element, Row, Rule,
-    Scrollable, Text,
+    button, executor, scrollable, text_input, Application, Button, Color, Column, Command, Element,
+    Row, Rule, Scrollable, Text, TextInput,
 };
 
 pub struct NixMiniGuiApp {
     displayed_section: DisplayedSection,
     config_manager: ConfigManager,
+    /// true once the user accepted or rejected the lock update of the ongoing save
+    lock_reviewed: bool,
 }
 
 pub type Flags = AppSetting;
 
+impl NixMiniGuiApp {
+    /// display the list of enabled config sources again, after one was enabled or disabled. The
+    /// selected config source stay selected if it is still enabled, with its conditions evaluated
+    /// again, as they may depend on the other config sources.
+    fn refresh_select_config(&mut self) {
+        let selected_key = match &self.displayed_section {
+            DisplayedSection::SelectConfig {
+                selected: Some(selected),
+                ..
+            } => Some(selected.key.clone()),
+            _ => None,
+        };
+        self.displayed_section = DisplayedSection::new_select_config(&self.config_manager);
+        if let Some(key) = selected_key {
+            if self.config_manager.is_source_enabled(&key) {
+                self.update(Message::ConfigurePackage(key));
+            };
+        };
+    }
+
+    /// display the inputs of the selected config source again, after their pending updates
+    /// changed
+    fn refresh_displayed_inputs(&mut self) {
+        if let DisplayedSection::SelectConfig {
+            selected: Some(selected),
+            ..
+        } = &mut self.displayed_section
+        {
+            selected.displayed_inputs = DisplayedInputs::new(&self.config_manager, &selected.key);
+        }
+    }
+}
+
 impl Application for NixMiniGuiApp {
     type Executor = executor::Default;
     type Message = Message;
@@ -30,6 +69,7 @@ impl Application for NixMiniGuiApp {
             Self {
                 displayed_section: DisplayedSection::new_select_config(&flags.config_manager),
                 config_manager: flags.config_manager,
+                lock_reviewed: false,
             },
             Command::none(),
         )
@@ -56,7 +96,7 @@ impl Application for NixMiniGuiApp {
                 } = &mut self.displayed_section
                 {
                     *selected_info = Some(DisplayedConfigInfo::new(
-                        &self.config_manager.get_config(&key).unwrap().0,
+                        self.config_manager.get_config(&key).unwrap().0,
                     ));
                     *selected_package = Some(key);
                 } else {
@@ -66,17 +106,22 @@ impl Application for NixMiniGuiApp {
             }
             Message::EnableConfig(key) => {
                 self.config_manager.enable_config(&key);
-                self.displayed_section = DisplayedSection::new_select_config(&self.config_manager);
+                self.refresh_select_config();
             }
             Message::ConfigurePackage(key) => {
                 if let DisplayedSection::SelectConfig { selected, .. } = &mut self.displayed_section
                 {
                     let config = &self.config_manager.get_config(&key).unwrap();
-                    let displayed_configuration =
-                        DisplayedConfiguration::new_from_source(&config.0, &config.2, key.clone());
+                    let displayed_configuration = DisplayedConfiguration::new_from_source(
+                        config.0,
+                        config.2,
+                        &self.config_manager,
+                        key.clone(),
+                    );
                     *selected = Some(SelectConfigSelected {
+                        displayed_inputs: DisplayedInputs::new(&self.config_manager, &key),
                         key,
-                        displayed_config_info: DisplayedConfigInfo::new(&config.0),
+                        displayed_config_info: DisplayedConfigInfo::new(config.0),
                         displayed_configuration,
                         scrollable_state: scrollable::State::new(),
                     });
@@ -87,24 +132,83 @@ impl Application for NixMiniGuiApp {
             }
             Message::DisableConfig(key) => {
                 self.config_manager.disable_config(&key);
-                self.displayed_section = DisplayedSection::new_select_config(&self.config_manager);
+                self.refresh_select_config();
             }
             Message::SetConfiguration(key, id, value) => {
                 self.config_manager
                     .set_configuration(key.clone(), id, value);
-                if let DisplayedSection::SelectConfig { selected, .. } = &mut self.displayed_section
+                if let DisplayedSection::SelectConfig {
+                    selected: Some(selected),
+                    ..
+                } = &mut self.displayed_section
                 {
-                    if let Some(selected) = selected {
-                        if selected.key == key {
-                            let config = &self.config_manager.get_config(&key).unwrap();
-                            selected
-                                .displayed_configuration
-                                .update(&config.0, &config.2);
-                        }
+                    if selected.key == key {
+                        let config = &self.config_manager.get_config(&key).unwrap();
+                        selected.displayed_configuration.update(
+                            config.0,
+                            config.2,
+                            &self.config_manager,
+                        );
                     }
                 }
             }
+            Message::SetNumberInput(key, id, text) => {
+                if let DisplayedSection::SelectConfig {
+                    selected: Some(selected),
+                    ..
+                } = &mut self.displayed_section
+                {
+                    if selected.key == key {
+                        if let Some(value) = selected
+                            .displayed_configuration
+                            .set_entered_number(&id, text)
+                        {
+                            return self.update(Message::SetConfiguration(key, id, value));
+                        };
+                    };
+                }
+            }
+            Message::SetListInput(key, id, text) => {
+                if let DisplayedSection::SelectConfig {
+                    selected: Some(selected),
+                    ..
+                } = &mut self.displayed_section
+                {
+                    if selected.key == key {
+                        selected.displayed_configuration.set_list_input(&id, text);
+                    };
+                }
+            }
+            Message::UpdateAllInputs => {
+                self.config_manager.update_all_inputs();
+                self.refresh_displayed_inputs();
+            }
+            Message::UpdateInputsOfConfig(key) => {
+                self.config_manager.update_inputs_of_config(&key);
+                self.refresh_displayed_inputs();
+            }
+            Message::UpdateInput(key, name) => {
+                self.config_manager.update_input(&key, &name);
+                self.refresh_displayed_inputs();
+            }
+            Message::ShowInputGraph(format) => {
+                self.displayed_section =
+                    DisplayedSection::new_input_graph(&self.config_manager, format);
+            }
+            Message::SetKeepUnusedPinsDays(text) => {
+                if let Ok(days) = text.parse() {
+                    self.config_manager.set_keep_unused_pins_days(days);
+                };
+                if let DisplayedSection::SelectConfig {
+                    keep_unused_pins_text,
+                    ..
+                } = &mut self.displayed_section
+                {
+                    *keep_unused_pins_text = text;
+                }
+            }
             Message::ValidateChange => {
+                self.lock_reviewed = false;
                 self.displayed_section =
                     DisplayedSection::new_progress_report("starting...".into());
             }
@@ -116,24 +220,60 @@ impl Application for NixMiniGuiApp {
                 self.displayed_section =
                     DisplayedSection::new_progress_report(format!("{} :\n{}", desc, log));
             }
-            Message::SetSaveProgress(None) => {
-                self.displayed_section = DisplayedSection::new_apply_finished();
+            Message::SetSaveProgress(Some(OngoingSaveProgressMessage::Failed(error))) => {
+                self.displayed_section = DisplayedSection::new_apply_failed(error);
+            }
+            Message::SetSaveProgress(Some(OngoingSaveProgressMessage::ReviewLockUpdate(
+                review,
+            ))) => {
+                self.displayed_section =
+                    DisplayedSection::new_review_lock_update(&self.config_manager, review);
+            }
+            Message::AcceptLockUpdate | Message::RejectLockUpdate => {
+                if let DisplayedSection::ReviewLockUpdate { review, .. } = &self.displayed_section {
+                    let new_lock = if let Message::AcceptLockUpdate = message {
+                        review.updated.clone()
+                    } else {
+                        review.rejected(self.config_manager.cached_fixed_input())
+                    };
+                    self.config_manager.set_cached_fixed_input(new_lock);
+                    self.lock_reviewed = true;
+                    self.displayed_section =
+                        DisplayedSection::new_progress_report("resuming...".into());
+                } else {
+                    //TODO: use log (error)
+                    println!("message {:?} received, but the screen isn't a ReviewLockUpdate. Ignoring this message.", message);
+                }
+            }
+            Message::SetSaveProgress(Some(OngoingSaveProgressMessage::Finished(
+                lock,
+                warnings,
+            ))) => {
+                // the save used a copy of the config manager, so the pins it fetched or pruned
+                // (and when) are only in the lock it wrote
+                self.config_manager.set_cached_fixed_input(lock);
+                self.config_manager.clear_pending_updates();
+                self.displayed_section = DisplayedSection::new_apply_finished(warnings);
             }
-            Message::Ignore => (),
+            Message::SetSaveProgress(None) | Message::Ignore => (),
             Message::Todo => todo!(),
         }
         Command::none()
     }
 
-    fn view(&mut self) -> Element<Self::Message> {
+    fn view(&mut self) -> Element<'_, Self::Message> {
         self.displayed_section.view()
     }
 
     fn subscription(&self) -> Subscription<Message> {
         match &self.displayed_section {
             DisplayedSection::SaveProgressReport { .. } => {
-                Subscription::from_recipe(self.config_manager.save_and_apply())
-                    .map(Message::SetSaveProgress)
+                Subscription::from_recipe(if self.lock_reviewed {
+                    self.config_manager.save_and_apply_with_reviewed_lock()
+                } else {
+                    self.config_manager.save_and_apply()
+                })
+                .map(Message::SetSaveProgress)
             }
             _ => Subscription::none(),
         }
@@ -144,9 +284,11 @@ pub struct SelectConfigSelected {
     key: String,
     displayed_config_info: DisplayedConfigInfo,
     displayed_configuration: DisplayedConfiguration,
+    displayed_inputs: DisplayedInputs,
     scrollable_state: scrollable::State,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum DisplayedSection {
     SelectConfig {
         add_new_config_button_state: button::State,
@@ -154,6 +296,10 @@ pub enum DisplayedSection {
         selected: Option<SelectConfigSelected>,
         uninstall_button_state: button::State,
         apply_change_state: button::State,
+        update_all_inputs_state: button::State,
+        /// the number of days the pins of uninstalled stuff are kept, as entered by the user
+        keep_unused_pins_text: String,
+        keep_unused_pins_state: text_input::State,
     },
     ChooseNewConfig {
         selectable_config: Vec<ButtonSelectableConfig>,
@@ -165,7 +311,30 @@ pub enum DisplayedSection {
     SaveProgressReport {
         progress_text: String,
     },
+    ReviewLockUpdate {
+        review: LockUpdateReview,
+        /// the changes, grouped by the label of the config source using them
+        changes: BTreeMap<Option<String>, Vec<(String, PinChange)>>,
+        scrollable_state: scrollable::State,
+        accept_state: button::State,
+        reject_state: button::State,
+    },
     ApplyFinished {
+        /// what the user should know about the applied configuration
+        warnings: Vec<String>,
+        continue_edit_state: button::State,
+    },
+    ApplyFailed {
+        error: String,
+        continue_edit_state: button::State,
+    },
+    InputGraph {
+        format: GraphFormat,
+        /// the rendered graph, or the error that prevented to generate it
+        rendered: String,
+        scrollable_state: scrollable::State,
+        dot_state: button::State,
+        json_state: button::State,
         continue_edit_state: button::State,
     },
 }
@@ -181,7 +350,7 @@ impl DisplayedSection {
                 .iter()
                 .map(|info| {
                     ButtonSelectableConfig::new(
-                        &info.0,
+                        info.0,
                         Message::SelectedPotentialInstallTarget(info.0.entry.id.to_string()),
                     )
                 })
@@ -201,7 +370,7 @@ impl DisplayedSection {
                 .iter()
                 .map(|info| {
                     ButtonSelectableConfig::new(
-                        &info.0,
+                        info.0,
                         Message::ConfigurePackage(info.0.entry.id.to_string()),
                     )
                 })
@@ -209,6 +378,9 @@ impl DisplayedSection {
             selected: None,
             uninstall_button_state: button::State::new(),
             apply_change_state: button::State::new(),
+            update_all_inputs_state: button::State::new(),
+            keep_unused_pins_text: config_manager.keep_unused_pins_days().to_string(),
+            keep_unused_pins_state: text_input::State::new(),
         }
     }
 
@@ -216,13 +388,65 @@ impl DisplayedSection {
         Self::SaveProgressReport { progress_text }
     }
 
-    fn new_apply_finished() -> Self {
+    fn new_review_lock_update(config_manager: &ConfigManager, review: LockUpdateReview) -> Self {
+        // the pruned inputs are listed separately
+        let mut diff = review.diff.clone();
+        for (input, _) in &review.pruned.pruned {
+            diff.changes.remove(input);
+        }
+        let changes = diff
+            .per_config_source(&config_manager.inputs_by_config_source())
+            .into_iter()
+            .map(|(source, changes)| {
+                (
+                    source.map(|id| {
+                        config_manager
+                            .get_config(&id)
+                            .map(|config| config.0.entry.label.clone())
+                            .unwrap_or(id)
+                    }),
+                    changes,
+                )
+            })
+            .collect();
+        Self::ReviewLockUpdate {
+            review,
+            changes,
+            scrollable_state: scrollable::State::new(),
+            accept_state: button::State::new(),
+            reject_state: button::State::new(),
+        }
+    }
+
+    fn new_apply_finished(warnings: Vec<String>) -> Self {
         Self::ApplyFinished {
+            warnings,
+            continue_edit_state: button::State::new(),
+        }
+    }
+
+    fn new_apply_failed(error: String) -> Self {
+        Self::ApplyFailed {
+            error,
+            continue_edit_state: button::State::new(),
+        }
+    }
+
+    fn new_input_graph(config_manager: &ConfigManager, format: GraphFormat) -> Self {
+        Self::InputGraph {
+            format,
+            rendered: match config_manager.input_graph() {
+                Ok(graph) => graph.render(format),
+                Err(err) => format!("can't generate the graph of the inputs: {}", err),
+            },
+            scrollable_state: scrollable::State::new(),
+            dot_state: button::State::new(),
+            json_state: button::State::new(),
             continue_edit_state: button::State::new(),
         }
     }
 
-    fn view(&mut self) -> Element<Message> {
+    fn view(&mut self) -> Element<'_, Message> {
         match self {
             Self::SelectConfig {
                 add_new_config_button_state,
@@ -230,6 +454,9 @@ impl DisplayedSection {
                 selected,
                 uninstall_button_state,
                 apply_change_state,
+                update_all_inputs_state,
+                keep_unused_pins_text,
+                keep_unused_pins_state,
             } => Row::new()
                 .push(
                     Column::new()
@@ -257,6 +484,8 @@ impl DisplayedSection {
                                     Scrollable::new(&mut selected.scrollable_state)
                                         .push(selected.displayed_config_info.view())
                                         .push(selected.displayed_configuration.view())
+                                        .push(Rule::horizontal(10))
+                                        .push(selected.displayed_inputs.view())
                                         .height(Length::Fill)
                                         .into(),
                                 )
@@ -274,10 +503,31 @@ impl DisplayedSection {
                                 .height(Length::Fill)
                                 .into()
                         })
-                        .push::<Element<_>>(
-                            Button::new(apply_change_state, Text::new("apply changes"))
-                                .on_press(Message::ValidateChange)
-                                .into(),
+                        .push(
+                            Row::new()
+                                .push(Text::new("keep the pins of uninstalled stuff for (days) :"))
+                                .push(TextInput::new(
+                                    keep_unused_pins_state,
+                                    "0",
+                                    keep_unused_pins_text,
+                                    Message::SetKeepUnusedPinsDays,
+                                ))
+                                .spacing(10),
+                        )
+                        .push(
+                            Row::new()
+                                .push(
+                                    Button::new(apply_change_state, Text::new("apply changes"))
+                                        .on_press(Message::ValidateChange),
+                                )
+                                .push(
+                                    Button::new(
+                                        update_all_inputs_state,
+                                        Text::new("update every inputs"),
+                                    )
+                                    .on_press(Message::UpdateAllInputs),
+                                )
+                                .spacing(10),
                         ),
                 )
                 .into(),
@@ -325,18 +575,115 @@ impl DisplayedSection {
             Self::SaveProgressReport { progress_text } => {
                 Text::new(progress_text.to_string()).into()
             }
+            Self::ReviewLockUpdate {
+                review,
+                changes,
+                scrollable_state,
+                accept_state,
+                reject_state,
+            } => Column::new()
+                .push(Text::new("the following pins will be updated :"))
+                .push::<Element<_>>({
+                    let mut scrollable = Scrollable::new(scrollable_state).height(Length::Fill);
+                    for (source, source_changes) in changes.iter() {
+                        scrollable = scrollable.push(Text::new(match source {
+                            Some(label) => format!("{} :", label),
+                            None => "no longer used :".to_string(),
+                        }));
+                        for (name, change) in source_changes {
+                            scrollable = scrollable.push(Text::new(format!(
+                                "    {} : {}",
+                                name,
+                                change.describe()
+                            )));
+                        }
+                    }
+                    if !review.pruned.is_empty() {
+                        scrollable = scrollable.push(Text::new(review.pruned.describe()));
+                    };
+                    scrollable.into()
+                })
+                .push(
+                    Row::new()
+                        .push(
+                            Button::new(accept_state, Text::new("accept the update"))
+                                .on_press(Message::AcceptLockUpdate),
+                        )
+                        .push(
+                            Button::new(reject_state, Text::new("keep the previous pins"))
+                                .on_press(Message::RejectLockUpdate),
+                        )
+                        .spacing(10),
+                )
+                .into(),
             Self::ApplyFinished {
+                warnings,
                 continue_edit_state,
-            } => Column::new()
-                .push::<Element<_>>(
+            } => {
+                let mut column = Column::new().push::<Element<_>>(
                     Text::new("application finished successfully".to_string()).into(),
-                )
+                );
+                for warning in warnings.iter() {
+                    column = column.push(
+                        Text::new(format!("warning: {}", warning))
+                            .color(Color::from_rgb(0.8, 0.0, 0.0)),
+                    );
+                }
+                column
+                    .push::<Element<_>>(
+                        Button::new(continue_edit_state, Text::new("continue edit".to_string()))
+                            .on_press(Message::SwitchScreenManageConfig)
+                            .into(),
+                    )
+                    .into()
+            }
+            Self::ApplyFailed {
+                error,
+                continue_edit_state,
+            } => Column::new()
+                .push::<Element<_>>(Text::new("failed to apply the changes :".to_string()).into())
+                .push::<Element<_>>(Text::new(error.to_string()).into())
                 .push::<Element<_>>(
                     Button::new(continue_edit_state, Text::new("continue edit".to_string()))
                         .on_press(Message::SwitchScreenManageConfig)
                         .into(),
                 )
                 .into(),
+            Self::InputGraph {
+                format,
+                rendered,
+                scrollable_state,
+                dot_state,
+                json_state,
+                continue_edit_state,
+            } => {
+                let mut dot_button = Button::new(dot_state, Text::new("DOT"));
+                let mut json_button = Button::new(json_state, Text::new("JSON"));
+                if *format != GraphFormat::Dot {
+                    dot_button = dot_button.on_press(Message::ShowInputGraph(GraphFormat::Dot));
+                };
+                if *format != GraphFormat::Json {
+                    json_button = json_button.on_press(Message::ShowInputGraph(GraphFormat::Json));
+                };
+                Column::new()
+                    .push(
+                        Row::new()
+                            .push(Text::new("graph of the inputs :"))
+                            .push(dot_button)
+                            .push(json_button)
+                            .spacing(10),
+                    )
+                    .push(
+                        Scrollable::new(scrollable_state)
+                            .height(Length::Fill)
+                            .push(Text::new(rendered.clone())),
+                    )
+                    .push(
+                        Button::new(continue_edit_state, Text::new("continue edit"))
+                            .on_press(Message::SwitchScreenManageConfig),
+                    )
+                    .into()
+            }
         }
     }
 }
@@ -358,7 +705,7 @@ impl ButtonSelectableConfig {
         }
     }
 
-    fn view(&mut self) -> Element<Message> {
+    fn view(&mut self) -> Element<'_, Message> {
         Button::new(&mut self.button_state, Text::new(&self.label))
             .on_press(self.message.clone())
             .into()
@@ -383,7 +730,7 @@ impl DisplayedConfigInfo {
         }
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let mut column = Column::new().push(Text::new(self.label.to_string())); //TODO: format
 
         if let Some(desc) = &self.desc {
@@ -403,3 +750,76 @@ impl DisplayedConfigInfo {
         column.into()
     }
 }
+
+pub struct DisplayedInputs {
+    key: String,
+    /// name, description of the lock, update button
+    inputs: Vec<(String, String, button::State)>,
+    update_all_state: button::State,
+    show_graph_state: button::State,
+}
+
+impl DisplayedInputs {
+    pub fn new(config_manager: &ConfigManager, key: &str) -> Self {
+        let inputs = match config_manager.get_config(key) {
+            Some((config_source, _, _)) => config_source
+                .entry
+                .effects
+                .inputs
+                .iter()
+                .map(|(name, declaration)| {
+                    let input = config_manager.effective_input(&declaration.distant);
+                    let mut lock = if config_manager.is_update_pending(input) {
+                        "will be updated on the next apply".to_string()
+                    } else if let Some(fixed) = config_manager.cached_fixed_input().get(input) {
+                        format!("locked to {}", fixed.revision())
+                    } else {
+                        "not locked yet".to_string()
+                    };
+                    if input != &declaration.distant {
+                        lock = format!("overridden by {}, {}", input.describe(), lock);
+                    };
+                    (name.clone(), lock, button::State::new())
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            key: key.to_string(),
+            inputs,
+            update_all_state: button::State::new(),
+            show_graph_state: button::State::new(),
+        }
+    }
+
+    fn view(&mut self) -> Element<'_, Message> {
+        let has_inputs = !self.inputs.is_empty();
+        let mut column = Column::new().push(Text::new("inputs :"));
+        for (name, lock, update_state) in self.inputs.iter_mut() {
+            column = column.push(
+                Row::new()
+                    .push(Text::new(format!("{} : {}", name, lock)))
+                    .push(
+                        Button::new(update_state, Text::new("update"))
+                            .on_press(Message::UpdateInput(self.key.clone(), name.clone())),
+                    )
+                    .spacing(10),
+            );
+        }
+        if has_inputs {
+            column = column.push(
+                Button::new(&mut self.update_all_state, Text::new("update all inputs"))
+                    .on_press(Message::UpdateInputsOfConfig(self.key.clone())),
+            );
+        };
+        column
+            .push(
+                Button::new(
+                    &mut self.show_graph_state,
+                    Text::new("show the input graph"),
+                )
+                .on_press(Message::ShowInputGraph(GraphFormat::Dot)),
+            )
+            .into()
+    }
+}
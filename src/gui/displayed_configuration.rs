@@ -1,11 +1,70 @@
 use crate::config_manager::UserConfiguration;
 use crate::config_source::{ConfigSource, Configuration, ConfigurationKind, RadioButtonPosibility};
+use crate::config_value::ConfigValue;
+use crate::gate::SourcesState;
 use crate::gui::Message;
-use iced::{text_input, Checkbox, Column, Element, Rule, Text, TextInput};
+use iced::{
+    button, slider, text_input, Button, Checkbox, Color, Column, Element, Row, Rule, Slider, Text,
+    TextInput,
+};
+
+/// the number of decimals to display for a float slider with this step
+fn step_decimals(step: f64) -> usize {
+    step.to_string()
+        .split_once('.')
+        .map(|(_, decimals)| decimals.len().min(6))
+        .unwrap_or(0)
+}
+
+/// the items of a list of strings
+fn list_items(value: &ConfigValue) -> Vec<String> {
+    match value {
+        ConfigValue::List(items) => items.iter().map(ToString::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// an item of a list of strings, with the state of its buttons
+pub struct DisplayedListItem {
+    text: String,
+    up_state: button::State,
+    down_state: button::State,
+    remove_state: button::State,
+}
+
+impl DisplayedListItem {
+    fn new_list(items: Vec<String>) -> Vec<Self> {
+        items
+            .into_iter()
+            .map(|text| Self {
+                text,
+                up_state: button::State::new(),
+                down_state: button::State::new(),
+                remove_state: button::State::new(),
+            })
+            .collect()
+    }
+}
+
+/// check if an option should be displayed, or return why its condition can't be evaluated (the
+/// option is then hidden, and the error displayed in its place)
+fn is_displayed(
+    config: &Configuration,
+    status: &UserConfiguration,
+    sources: &dyn SourcesState,
+) -> Result<bool, String> {
+    config.condition.evaluate(status, sources).map_err(|err| {
+        format!(
+            "can't evaluate the condition of the option {:?}: {}",
+            config.label, err
+        )
+    })
+}
 
 pub enum DisplayedConfiguration {
     Group {
-        configs: Vec<(bool, Self)>,
+        /// every option, with whether it is displayed (see `is_displayed`)
+        configs: Vec<(Result<bool, String>, Self)>,
     },
     RadioButton {
         label: String,
@@ -27,30 +86,66 @@ pub enum DisplayedConfiguration {
         entered: String,
         placeholder: String,
         state: text_input::State,
+        /// if the entered text is masked, for the secrets
+        masked: bool,
+        /// if this is a secret passed as is to the package, and so put in the nix store
+        inline_secret: bool,
+    },
+    /// an integer or float option. Displayed as a slider if it is bounded, as a number field
+    /// otherwise.
+    Number {
+        label: String,
+        id: String,
+        key: String,
+        kind: ConfigurationKind,
+        /// the text entered in the number field, which may not be valid
+        entered: String,
+        /// why the entered text isn't valid
+        error: Option<String>,
+        text_state: text_input::State,
+        slider_state: slider::State,
+    },
+    /// a list of strings, with buttons to add, remove and move its items
+    StringList {
+        label: String,
+        id: String,
+        key: String,
+        kind: ConfigurationKind,
+        items: Vec<DisplayedListItem>,
+        /// the item being entered, not added yet
+        new_item: String,
+        /// why the entered item can't be added
+        error: Option<String>,
+        new_item_state: text_input::State,
+        add_state: button::State,
     },
 }
 
 impl DisplayedConfiguration {
+    /// `sources` is the state of the other config sources, used by the conditions that depend on
+    /// them
     pub fn new_from_source(
         config_source: &ConfigSource,
         status: &UserConfiguration,
+        sources: &dyn SourcesState,
         key: String,
     ) -> Self {
-        Self::new_top_level_group(key, &config_source.entry.configurations, status)
+        Self::new_top_level_group(key, &config_source.entry.configurations, status, sources)
     }
 
     fn new_top_level_group(
         conf_key: String,
         configs: &[Configuration],
         status: &UserConfiguration,
+        sources: &dyn SourcesState,
     ) -> Self {
         Self::Group {
             configs: configs
                 .iter()
                 .map(move |c| {
                     (
-                        c.condition.evaluate(status),
-                        Self::new_from_configuration(conf_key.clone(), c, status),
+                        is_displayed(c, status, sources),
+                        Self::new_from_configuration(conf_key.clone(), c, status, sources),
                     )
                 })
                 .collect(),
@@ -61,6 +156,7 @@ impl DisplayedConfiguration {
         conf_key: String,
         config: &Configuration,
         status: &UserConfiguration,
+        sources: &dyn SourcesState,
     ) -> Self {
         match &config.kind {
             ConfigurationKind::RadioButton {
@@ -73,8 +169,8 @@ impl DisplayedConfiguration {
                 possibilities: possibilities.clone(),
                 selected: status
                     .get(&config.id)
-                    .unwrap_or(&default.clone())
-                    .to_string(),
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| default.clone()),
             },
             ConfigurationKind::Checkbox { default } => Self::Checkbox {
                 label: config.label.clone(),
@@ -82,7 +178,7 @@ impl DisplayedConfiguration {
                 key: conf_key,
                 enabled: status
                     .get(&config.id)
-                    .map(|x| x == "true")
+                    .map(ConfigValue::is_true)
                     .unwrap_or(*default),
             },
             ConfigurationKind::Textbox { default } => Self::Textbox {
@@ -91,10 +187,58 @@ impl DisplayedConfiguration {
                 key: conf_key,
                 entered: status
                     .get(&config.id)
-                    .cloned()
+                    .map(ToString::to_string)
                     .unwrap_or_else(|| default.to_string()),
                 state: text_input::State::new(),
                 placeholder: default.to_string(),
+                masked: false,
+                inline_secret: false,
+            },
+            ConfigurationKind::Secret { inline } => Self::Textbox {
+                label: config.label.clone(),
+                id: config.id.clone(),
+                key: conf_key,
+                entered: status
+                    .get(&config.id)
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+                state: text_input::State::new(),
+                placeholder: String::new(),
+                masked: true,
+                inline_secret: *inline,
+            },
+            ConfigurationKind::StringList { .. } => Self::StringList {
+                label: config.label.clone(),
+                id: config.id.clone(),
+                key: conf_key,
+                kind: config.kind.clone(),
+                items: DisplayedListItem::new_list(
+                    status
+                        .get(&config.id)
+                        .cloned()
+                        .or_else(|| config.kind.default_value())
+                        .map(|value| list_items(&value))
+                        .unwrap_or_default(),
+                ),
+                new_item: String::new(),
+                error: None,
+                new_item_state: text_input::State::new(),
+                add_state: button::State::new(),
+            },
+            ConfigurationKind::Integer { .. } | ConfigurationKind::Float { .. } => Self::Number {
+                label: config.label.clone(),
+                id: config.id.clone(),
+                key: conf_key,
+                kind: config.kind.clone(),
+                entered: status
+                    .get(&config.id)
+                    .cloned()
+                    .or_else(|| config.kind.default_value())
+                    .map(|value| value.to_string())
+                    .unwrap_or_default(),
+                error: None,
+                text_state: text_input::State::new(),
+                slider_state: slider::State::new(),
             },
             ConfigurationKind::Group { configurations } => {
                 let conf_key_clone = conf_key;
@@ -103,11 +247,12 @@ impl DisplayedConfiguration {
                         .iter()
                         .map(move |c| {
                             (
-                                c.condition.evaluate(status),
+                                is_displayed(c, status, sources),
                                 Self::new_from_configuration(
-                                    (&conf_key_clone).to_string(),
+                                    conf_key_clone.to_string(),
                                     c,
                                     status,
+                                    sources,
                                 ),
                             )
                         })
@@ -117,27 +262,37 @@ impl DisplayedConfiguration {
         }
     }
 
-    pub fn update(&mut self, config_source: &ConfigSource, status: &UserConfiguration) {
+    pub fn update(
+        &mut self,
+        config_source: &ConfigSource,
+        status: &UserConfiguration,
+        sources: &dyn SourcesState,
+    ) {
         if let Self::Group { configs } = self {
             for (disp, config) in configs
                 .iter_mut()
                 .zip(config_source.entry.configurations.iter())
             {
-                disp.0 = config.condition.evaluate(status);
-                disp.1.update_component(config, status);
+                disp.0 = is_displayed(config, status, sources);
+                disp.1.update_component(config, status, sources);
             }
         } else {
             panic!("called update on a non Group element (that contains all the configurations)");
         }
     }
 
-    fn update_component(&mut self, config: &Configuration, status: &UserConfiguration) {
+    fn update_component(
+        &mut self,
+        config: &Configuration,
+        status: &UserConfiguration,
+        sources: &dyn SourcesState,
+    ) {
         match self {
             Self::Group { configs } => {
                 if let ConfigurationKind::Group { configurations } = &config.kind {
                     for (disp, sub_config) in configs.iter_mut().zip(configurations.iter()) {
-                        disp.0 = config.condition.evaluate(status);
-                        disp.1.update_component(sub_config, status);
+                        disp.0 = is_displayed(sub_config, status, sources);
+                        disp.1.update_component(sub_config, status, sources);
                     }
                 } else {
                     panic!()
@@ -145,36 +300,125 @@ impl DisplayedConfiguration {
             }
             Self::RadioButton { selected, id, .. } => {
                 if let Some(s) = status.get(id) {
-                    *selected = s.clone();
+                    *selected = s.to_string();
                 };
             }
             Self::Checkbox { enabled, id, .. } => {
                 if let Some(e) = status.get(id) {
-                    *enabled = e == "true";
+                    *enabled = e.is_true();
                 };
             }
             Self::Textbox { entered, id, .. } => {
                 if let Some(e) = status.get(id) {
-                    *entered = e.clone();
+                    *entered = e.to_string();
                 }
             }
+            Self::Number {
+                entered, error, id, ..
+            } => {
+                // keep what is being typed (like "1." for "1.5") if it is the saved value
+                if let Some(value) = status.get(id) {
+                    if value.as_f64() != entered.trim().parse().ok() {
+                        *entered = value.to_string();
+                        *error = None;
+                    };
+                };
+            }
+            Self::StringList {
+                id,
+                items,
+                new_item,
+                error,
+                ..
+            } => {
+                if let Some(value) = status.get(id) {
+                    let new_items = list_items(value);
+                    if new_items.iter().ne(items.iter().map(|item| &item.text)) {
+                        // the entered item has been added
+                        if new_items.len() > items.len() {
+                            new_item.clear();
+                            *error = None;
+                        };
+                        *items = DisplayedListItem::new_list(new_items);
+                    };
+                };
+            }
         }
     }
 
-    pub fn view(&mut self) -> Element<Message> {
+    /// set the item entered in the list of strings `id`, and check that it can be added. Return
+    /// false if there is no such list.
+    pub fn set_list_input(&mut self, id: &str, text: String) -> bool {
+        match self {
+            Self::Group { configs } => configs
+                .iter_mut()
+                .any(|(_, config)| config.set_list_input(id, text.clone())),
+            Self::StringList {
+                id: list_id,
+                kind,
+                items,
+                new_item,
+                error,
+                ..
+            } if list_id == id => {
+                let mut added: Vec<ConfigValue> = items
+                    .iter()
+                    .map(|item| ConfigValue::String(item.text.clone()))
+                    .collect();
+                added.push(ConfigValue::String(text.clone()));
+                *error = if text.is_empty() {
+                    None
+                } else {
+                    kind.check_value(&ConfigValue::List(added))
+                        .err()
+                        .map(|err| err.to_string())
+                };
+                *new_item = text;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// set the text entered in the number field of the option `id`. Return the entered value if
+    /// it is valid, None if it isn't or if there is no such number option.
+    pub fn set_entered_number(&mut self, id: &str, text: String) -> Option<ConfigValue> {
+        match self {
+            Self::Group { configs } => configs
+                .iter_mut()
+                .find_map(|(_, config)| config.set_entered_number(id, text.clone())),
+            Self::Number {
+                id: number_id,
+                kind,
+                entered,
+                error,
+                ..
+            } if number_id == id => {
+                let value = kind.parse_value(&text);
+                *error = value.as_ref().err().map(ToString::to_string);
+                *entered = text;
+                value.ok()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, Message> {
         match self {
             Self::Group { configs } => {
                 let mut childrens = Vec::new();
-                for (count, config) in configs
-                    .iter_mut()
-                    .filter(|x| x.0)
-                    .map(|x| &mut x.1)
-                    .enumerate()
+                for (count, (displayed, config)) in
+                    configs.iter_mut().filter(|x| x.0 != Ok(false)).enumerate()
                 {
                     if count != 0 {
                         childrens.push(Rule::horizontal(10).into())
                     }
-                    childrens.push(config.view())
+                    childrens.push(match displayed {
+                        Err(error) => Text::new(error.clone())
+                            .color(Color::from_rgb(0.8, 0.0, 0.0))
+                            .into(),
+                        Ok(_) => config.view(),
+                    })
                 }
                 Column::with_children(childrens).into()
             }
@@ -198,9 +442,9 @@ impl DisplayedConfiguration {
                         let value_clone = possibility.id.clone();
                         Checkbox::new(checked, possibility.label.clone(), move |_| {
                             Message::SetConfiguration(
-                                (&key_clone).to_string(),
-                                (&id_clone).to_string(),
-                                (&value_clone).to_string(),
+                                key_clone.to_string(),
+                                id_clone.to_string(),
+                                ConfigValue::String(value_clone.to_string()),
                             )
                         })
                         .into()
@@ -218,13 +462,9 @@ impl DisplayedConfiguration {
                 let id_clone = id.clone();
                 Checkbox::new(*enabled, label.to_string(), move |s| {
                     Message::SetConfiguration(
-                        (&key_clone).to_string(),
-                        (&id_clone).to_string(),
-                        if s {
-                            "true".to_string()
-                        } else {
-                            "false".to_string()
-                        },
+                        key_clone.to_string(),
+                        id_clone.to_string(),
+                        ConfigValue::Bool(s),
                     )
                 })
                 .into()
@@ -236,19 +476,184 @@ impl DisplayedConfiguration {
                 entered,
                 placeholder,
                 state,
+                masked,
+                inline_secret,
+            } => {
+                let key_clone = key.clone();
+                let id_clone = id.clone();
+                let mut input = TextInput::new(state, placeholder, entered, move |v| {
+                    Message::SetConfiguration(
+                        key_clone.to_string(),
+                        id_clone.to_string(),
+                        ConfigValue::String(v),
+                    )
+                });
+                if *masked {
+                    input = input.password();
+                };
+                let mut column = Column::new().push(Text::new(label.to_string())).push(input);
+                if *inline_secret {
+                    column = column.push(
+                        Text::new("warning: this package need this secret as is. It will be copied in the nix store, where every user of this computer can read it.")
+                            .color(Color::from_rgb(0.8, 0.0, 0.0)),
+                    );
+                };
+                column.into()
+            }
+            Self::StringList {
+                label,
+                id,
+                key,
+                items,
+                new_item,
+                error,
+                new_item_state,
+                add_state,
+                ..
             } => {
+                let texts: Vec<String> = items.iter().map(|item| item.text.clone()).collect();
+                let set_list = |list: Vec<String>| {
+                    Message::SetConfiguration(
+                        key.clone(),
+                        id.clone(),
+                        ConfigValue::List(list.into_iter().map(ConfigValue::String).collect()),
+                    )
+                };
+                let mut column = Column::new().push(Text::new(format!("{} :", label)));
+                let count = items.len();
+                for (position, item) in items.iter_mut().enumerate() {
+                    let mut up = Button::new(&mut item.up_state, Text::new("up"));
+                    if position > 0 {
+                        let mut moved = texts.clone();
+                        moved.swap(position - 1, position);
+                        up = up.on_press(set_list(moved));
+                    };
+                    let mut down = Button::new(&mut item.down_state, Text::new("down"));
+                    if position + 1 < count {
+                        let mut moved = texts.clone();
+                        moved.swap(position, position + 1);
+                        down = down.on_press(set_list(moved));
+                    };
+                    let mut removed = texts.clone();
+                    removed.remove(position);
+                    let remove = Button::new(&mut item.remove_state, Text::new("remove"))
+                        .on_press(set_list(removed));
+                    column = column.push(
+                        Row::new()
+                            .spacing(10)
+                            .push(Text::new(item.text.clone()))
+                            .push(up)
+                            .push(down)
+                            .push(remove),
+                    );
+                }
+                let mut add = Button::new(add_state, Text::new("add"));
+                if error.is_none() && !new_item.is_empty() {
+                    let mut added = texts.clone();
+                    added.push(new_item.clone());
+                    add = add.on_press(set_list(added));
+                };
                 let key_clone = key.clone();
                 let id_clone = id.clone();
-                Column::new()
-                    .push(Text::new(label.to_string()))
-                    .push(TextInput::new(state, &placeholder, &entered, move |v| {
-                        Message::SetConfiguration(
-                            (&key_clone).to_string(),
-                            (&id_clone).to_string(),
-                            v,
-                        )
-                    }))
-                    .into()
+                column = column.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(TextInput::new(
+                            new_item_state,
+                            "new item",
+                            new_item,
+                            move |v| {
+                                Message::SetListInput(
+                                    key_clone.to_string(),
+                                    id_clone.to_string(),
+                                    v,
+                                )
+                            },
+                        ))
+                        .push(add),
+                );
+                if let Some(error) = error {
+                    column = column.push(
+                        Text::new(format!("can't add this item: {}", error))
+                            .color(Color::from_rgb(0.8, 0.0, 0.0)),
+                    );
+                };
+                column.into()
+            }
+            Self::Number {
+                label,
+                id,
+                key,
+                kind,
+                entered,
+                error,
+                text_state,
+                slider_state,
+            } => {
+                let key_clone = key.clone();
+                let id_clone = id.clone();
+                let bounds = match kind {
+                    ConfigurationKind::Integer {
+                        min: Some(min),
+                        max: Some(max),
+                        step,
+                        ..
+                    } => Some((*min as f64, *max as f64, *step as f64, 0)),
+                    ConfigurationKind::Float {
+                        min: Some(min),
+                        max: Some(max),
+                        step,
+                        ..
+                    } => {
+                        let step = step.map(|step| step.0).unwrap_or((max.0 - min.0) / 100.0);
+                        Some((min.0, max.0, step, step_decimals(step)))
+                    }
+                    _ => None,
+                };
+                let is_integer = matches!(kind, ConfigurationKind::Integer { .. });
+                match bounds {
+                    Some((min, max, step, decimals)) => {
+                        let value = entered.trim().parse().unwrap_or(min).max(min).min(max);
+                        Column::new()
+                            .push(Text::new(format!("{} : {}", label, entered)))
+                            .push(
+                                Slider::new(slider_state, min..=max, value, move |v: f64| {
+                                    Message::SetConfiguration(
+                                        key_clone.to_string(),
+                                        id_clone.to_string(),
+                                        if is_integer {
+                                            ConfigValue::Integer(v.round() as i64)
+                                        } else {
+                                            ConfigValue::Float(
+                                                format!("{:.*}", decimals, v).parse().unwrap_or(v),
+                                            )
+                                        },
+                                    )
+                                })
+                                .step(step),
+                            )
+                            .into()
+                    }
+                    None => {
+                        let mut column =
+                            Column::new()
+                                .push(Text::new(label.to_string()))
+                                .push(TextInput::new(text_state, "", entered, move |v| {
+                                    Message::SetNumberInput(
+                                        key_clone.to_string(),
+                                        id_clone.to_string(),
+                                        v,
+                                    )
+                                }));
+                        if let Some(error) = error {
+                            column = column.push(
+                                Text::new(format!("invalid value: {}", error))
+                                    .color(Color::from_rgb(0.8, 0.0, 0.0)),
+                            );
+                        };
+                        column.into()
+                    }
+                }
             }
         }
     }
@@ -5,10 +5,12 @@ mod displayed_configuration;
 pub use displayed_configuration::DisplayedConfiguration;
 
 use crate::config_manager::ConfigManager;
+use crate::config_value::ConfigValue;
 pub struct AppSetting {
     pub config_manager: ConfigManager,
 }
 
+use crate::input_graph::GraphFormat;
 use crate::ongoing_save::OngoingSaveProgressMessage;
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -18,9 +20,18 @@ pub enum Message {
     EnableConfig(String),
     DisableConfig(String),
     ConfigurePackage(String),
-    SetConfiguration(String, String, String), //config key, id, value
+    SetConfiguration(String, String, ConfigValue), //config key, id, value
+    SetNumberInput(String, String, String),        //config key, id, entered text
+    SetListInput(String, String, String),          //config key, id, entered item
     ValidateChange,
+    UpdateAllInputs,
+    UpdateInputsOfConfig(String),
+    UpdateInput(String, String), //config key, input name
+    SetKeepUnusedPinsDays(String),
+    ShowInputGraph(GraphFormat),
     SetSaveProgress(Option<OngoingSaveProgressMessage>),
+    AcceptLockUpdate,
+    RejectLockUpdate,
     Ignore,
     Todo,
 }
@@ -0,0 +1,119 @@
+use crate::nixtool::generate_dict_from_btreemap;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// How a fetched input is turned into the value passed to the packages
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ImportKind {
+    /// The input is a function that take its dependancies: `import <input> { <dependancies> }`.
+    /// `arguments` is an optional nix attribute set merged with the dependancies, like
+    /// `{ config.allowUnfree = true; }`.
+    Function {
+        #[serde(default = "Option::default")]
+        arguments: Option<String>,
+    },
+    /// The input evaluate to a value that isn't a function, like a plain attribute set:
+    /// `import <input>`
+    Import,
+    /// The input is used as a path, without being imported
+    Path,
+    /// The input is a package built with `callPackage` of the given package set, which should be
+    /// one of its dependancies. The other dependancies and the `arguments` are passed explicitly.
+    CallPackage {
+        package_set: String,
+        #[serde(default = "Option::default")]
+        arguments: Option<String>,
+    },
+}
+
+impl Default for ImportKind {
+    fn default() -> Self {
+        Self::Function { arguments: None }
+    }
+}
+
+/// the dependancies as a nix attribute set, merged with the additional arguments if any
+fn generate_arguments(
+    dependancies: &BTreeMap<String, String>,
+    arguments: &Option<String>,
+) -> String {
+    let dependancies = generate_dict_from_btreemap(dependancies);
+    match arguments {
+        Some(arguments) => format!("({} // ({}))", dependancies, arguments),
+        None => dependancies,
+    }
+}
+
+impl ImportKind {
+    /// the package set this input is built with, if it use `callPackage`
+    pub fn package_set(&self) -> Option<&str> {
+        match self {
+            Self::CallPackage { package_set, .. } => Some(package_set),
+            _ => None,
+        }
+    }
+
+    /// generate the nix expression of the input, given the expression that fetch it, and the name
+    /// of its dependancies by argument name
+    pub fn generate_nix(&self, fetch: &str, dependancies: &BTreeMap<String, String>) -> String {
+        match self {
+            Self::Function { arguments } => format!(
+                "import {} {}",
+                fetch,
+                generate_arguments(dependancies, arguments)
+            ),
+            Self::Import => format!("import {}", fetch),
+            Self::Path => fetch.to_string(),
+            Self::CallPackage {
+                package_set,
+                arguments,
+            } => {
+                let mut explicit = dependancies.clone();
+                let package_set = explicit
+                    .remove(package_set)
+                    .unwrap_or_else(|| package_set.clone());
+                format!(
+                    "{}.callPackage {} {}",
+                    package_set,
+                    fetch,
+                    generate_arguments(&explicit, arguments)
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn test_generate_import() {
+    let mut dependancies = BTreeMap::new();
+    dependancies.insert("pkgs".to_string(), "nixpkgs".to_string());
+    dependancies.insert("lib".to_string(), "mylib".to_string());
+    let fetch = "(builtins.fetchTarball \"x\")";
+    assert_eq!(
+        ImportKind::default().generate_nix(fetch, &dependancies),
+        format!("import {} {{\nlib = mylib;\npkgs = nixpkgs;\n}}", fetch)
+    );
+    let kind: ImportKind = serde_json::from_str(
+        r#"{"type": "Function", "arguments": "{ config.allowUnfree = true; }"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        kind.generate_nix(fetch, &BTreeMap::new()),
+        format!(
+            "import {} ({{\n}} // ({{ config.allowUnfree = true; }}))",
+            fetch
+        )
+    );
+    assert_eq!(
+        ImportKind::Import.generate_nix(fetch, &dependancies),
+        format!("import {}", fetch)
+    );
+    assert_eq!(ImportKind::Path.generate_nix(fetch, &dependancies), fetch);
+    let kind: ImportKind =
+        serde_json::from_str(r#"{"type": "CallPackage", "package_set": "pkgs"}"#).unwrap();
+    assert_eq!(
+        kind.generate_nix(fetch, &dependancies),
+        format!("nixpkgs.callPackage {} {{\nlib = mylib;\n}}", fetch)
+    );
+}
@@ -1,67 +1,681 @@
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
+use crate::download::{download, unpack_tarball, DownloadError, UnpackError};
+use crate::flake::{lock_flake, FlakeError, FlakeReference};
+use crate::git::{resolve_reference, GitError};
+use crate::nar::{flat_sha256, nar_sha256, to_nix_base32};
+use crate::nix_path::{read_channel_metadata, resolve_system_wide};
 use crate::nixtool::escape_string;
+use crate::temp_dir::TempDir;
+use std::io;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum FetchInputError {
+        Git { url: String, reference: String, err: GitError } {
+            cause(err)
+            display(me) -> ("can't resolve the reference {:?} of the git repository {:?}: {}", reference, url, err)
+        }
+        Download(err: DownloadError) {
+            from()
+            cause(err)
+            display(me) -> ("{}", err)
+        }
+        Unpack { url: String, err: UnpackError } {
+            cause(err)
+            display(me) -> ("can't unpack the archive downloaded from {:?}: {}", url, err)
+        }
+        CantHash { path: PathBuf, err: io::Error } {
+            cause(err)
+            display(me) -> ("can't compute the hash of {:?}: {}", path, err)
+        }
+        Flake { reference: String, err: FlakeError } {
+            cause(err)
+            display(me) -> ("can't lock the flake {:?}: {}", reference, err)
+        }
+        NotInNixPath { package: String } {
+            display(me) -> ("can't find <{}> in the NIX_PATH or in the channels", package)
+        }
+    }
+}
+
+fn default_git_reference() -> String {
+    "HEAD".to_string()
+}
+
+/// where the tarball of a nixpkgs commit is downloaded from, followed by `<commit>.tar.gz`
+const NIXPKGS_ARCHIVE_URL: &str = "https://github.com/NixOS/nixpkgs/archive/";
 
 #[derive(Hash, PartialEq, Eq, Clone, PartialOrd, Ord, Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum UpdatableInput {
-    //Git(repo, ref)
+    /// A git repository. The reference is resolved to a commit when locked.
+    Git {
+        url: String,
+        /// a branch, a tag or a commit hash. Default to the HEAD of the repository.
+        #[serde(rename = "ref", default = "default_git_reference")]
+        reference: String,
+    },
+    /// A file downloaded from an url. The content hash is computed when locked.
+    Url {
+        url: String,
+        /// if true, the file is a tarball that will be unpacked (like `builtins.fetchTarball`)
+        #[serde(default = "bool::default")]
+        unpack: bool,
+    },
     LocalPath {
         path: PathBuf,
         #[serde(default = "bool::default")]
         is_absolute: bool,
     },
+    /// A library in the nix search path, like `<nixpkgs>`. Unless it is floating, it is locked to
+    /// the nixpkgs commit of the channel it currently resolve to (or to the content of its store
+    /// path if the commit is unknown).
     SystemWide {
         package: String,
+        /// if true, the input isn't locked, and will always use the current version of the
+        /// library on the machine
+        #[serde(default = "bool::default")]
+        floating: bool,
+    },
+    /// A nix flake, like `path:/src/foo` or `git+file:///repo?ref=main`. It is locked to a fully
+    /// resolved reference with its narHash. The flake is used as a value, so the `depend_on` of
+    /// its declaration are ignored (the flake have its own inputs).
+    Flake {
+        reference: String,
+        /// the attribute path of the output to use, like `lib` or `packages.x86_64-linux.hello`.
+        /// The whole flake is used if unset.
+        #[serde(default = "Option::default")]
+        output: Option<String>,
     },
 }
 
+/// Make `path` absolute, resolving it relative to `base_dir` (that may itself be relative to the
+/// current directory). The symlinks are resolved if the path exist.
+pub fn make_absolute(base_dir: &Path, path: &Path) -> PathBuf {
+    let path = base_dir.join(path);
+    std::fs::canonicalize(&path).unwrap_or_else(|_| {
+        if path.is_relative() {
+            std::env::current_dir()
+                .map(|current_dir| current_dir.join(&path))
+                .unwrap_or(path)
+        } else {
+            path
+        }
+    })
+}
+
 impl UpdatableInput {
     pub fn ensure_path_is_absolute(&mut self, base_dir: &Path) {
         match self {
             Self::LocalPath { path, .. } => {
-                let mut path = path.clone();
-                if path.is_relative() {
-                    path = base_dir.join(&path);
-                };
                 *self = Self::LocalPath {
-                    path,
+                    path: make_absolute(base_dir, path),
                     is_absolute: true,
                 };
             }
+            Self::Flake { reference, .. } => {
+                if let Ok(mut parsed) = FlakeReference::parse(reference) {
+                    if let Some(path) = parsed.local_path().filter(|path| path.is_relative()) {
+                        parsed.set_local_path(&make_absolute(base_dir, &path));
+                        *reference = parsed.to_string();
+                    }
+                }
+            }
             _ => (),
         }
     }
 
-    pub async fn get_latest(&self) -> FixedInput {
+    /// A short human readable description of this input
+    pub fn describe(&self) -> String {
         match self {
+            Self::Git { url, reference } => format!("{} ({})", url, reference),
+            Self::Url { url, .. } => url.clone(),
+            Self::LocalPath { path, .. } => path.to_string_lossy().to_string(),
+            Self::SystemWide { package, .. } => format!("<{}>", package),
+            Self::Flake {
+                reference,
+                output: Some(output),
+            } => format!("{}#{}", reference, output),
+            Self::Flake { reference, .. } => reference.clone(),
+        }
+    }
+
+    pub async fn get_latest(&self) -> Result<FixedInput, FetchInputError> {
+        Ok(match self {
+            Self::Git { url, reference } => {
+                let resolved = resolve_reference(url, reference).await.map_err(|err| {
+                    FetchInputError::Git {
+                        url: url.clone(),
+                        reference: reference.clone(),
+                        err,
+                    }
+                })?;
+                FixedInput::Git {
+                    url: url.clone(),
+                    rev: resolved.rev,
+                    hash: resolved.tree,
+                }
+            }
+            Self::Url { url, unpack } => {
+                if *unpack {
+                    FixedInput::Tarball {
+                        url: url.clone(),
+                        sha256: hash_tarball(url).await?,
+                    }
+                } else {
+                    FixedInput::File {
+                        url: url.clone(),
+                        sha256: to_nix_base32(&flat_sha256(&download(url).await?)),
+                    }
+                }
+            }
             Self::LocalPath { path, is_absolute } => {
                 if !is_absolute {
                     panic!("updateinput.get_latest, the path {:?} haven't been normalized (explicitly made absolute)", path);
                 };
-                FixedInput::LocalPath(path.to_string_lossy().to_string())
+                FixedInput::Path {
+                    path: path.to_string_lossy().to_string(),
+                    sha256: hash_local_path(path).await?,
+                    name: store_path_name(path),
+                }
             }
-            Self::SystemWide { package } => FixedInput::SystemWide(package.to_string()),
-        }
+            Self::SystemWide {
+                package,
+                floating: true,
+            } => FixedInput::SystemWide(package.to_string()),
+            Self::SystemWide {
+                package,
+                floating: false,
+            } => {
+                let store_path =
+                    resolve_system_wide(package).ok_or_else(|| FetchInputError::NotInNixPath {
+                        package: package.clone(),
+                    })?;
+                FixedInput::pin_channel(package, &store_path, NIXPKGS_ARCHIVE_URL).await?
+            }
+            Self::Flake { reference, output } => {
+                let locked = lock_flake(reference)
+                    .await
+                    .map_err(|err| FetchInputError::Flake {
+                        reference: reference.clone(),
+                        err,
+                    })?;
+                FixedInput::Flake {
+                    locked: locked.url,
+                    nar_hash: locked.nar_hash,
+                    output: output.clone(),
+                }
+            }
+        })
+    }
+}
+
+/// Download and unpack the tarball at `url`, and compute the NAR sha256 of its content (like
+/// `builtins.fetchTarball`), in the nix base32 format
+async fn hash_tarball(url: &str) -> Result<String, FetchInputError> {
+    let content = download(url).await?;
+    let url = url.to_string();
+    async_std::task::spawn_blocking(move || {
+        let destination = TempDir::new("tarball").map_err(|err| FetchInputError::CantHash {
+            path: std::env::temp_dir(),
+            err,
+        })?;
+        let root = unpack_tarball(&content, destination.path())
+            .map_err(|err| FetchInputError::Unpack { url, err })?;
+        nar_sha256(&root)
+            .map(|hash| to_nix_base32(&hash))
+            .map_err(|err| FetchInputError::CantHash { path: root, err })
+    })
+    .await
+}
+
+/// Compute the NAR sha256 of a local path, in the nix base32 format
+async fn hash_local_path(path: &Path) -> Result<String, FetchInputError> {
+    let path = path.to_path_buf();
+    async_std::task::spawn_blocking(move || {
+        nar_sha256(&path)
+            .map(|hash| to_nix_base32(&hash))
+            .map_err(|err| FetchInputError::CantHash { path, err })
+    })
+    .await
+}
+
+/// Generate a valid store path name from the file name of the path
+fn store_path_name(path: &Path) -> String {
+    let name: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "+-._?=".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "source".to_string()
+    } else {
+        name.to_string()
     }
 }
 
-#[derive(Serialize, Deserialize, Hash, Clone, Debug)]
+/// Whether a locked input still match what it was locked to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    /// the input is locked, and still match the lock
+    UpToDate,
+    /// the input is locked, but its content changed since then (only checked for local path)
+    Stale,
+    /// the input is locked, but the lock is older than what its update policy allow
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Hash, Clone, Debug, PartialEq, Eq)]
 pub enum FixedInput {
-    /// A path to a local folder or file. The file/folder itself is not fixed !
+    /// A commit of a git repository
+    Git {
+        url: String,
+        /// the commit hash
+        rev: String,
+        /// the hash of the git tree of this commit
+        hash: String,
+    },
+    /// An unpacked tarball, with the sha256 of its content (in the nix base32 format)
+    Tarball { url: String, sha256: String },
+    /// A downloaded file, with the sha256 of its content (in the nix base32 format)
+    File { url: String, sha256: String },
+    /// A path to a local folder or file, with the sha256 of its NAR serialisation (in the nix
+    /// base32 format)
+    Path {
+        path: String,
+        sha256: String,
+        /// the name of the path once added to the store
+        name: String,
+    },
+    /// A path to a local folder or file, as written by older lock file. The file/folder itself is
+    /// not fixed, and it is always considered stale.
     LocalPath(String),
     /// A library in the nix search path. The library itself isn't fixed !
     SystemWide(String),
+    /// A library of the nix search path, locked to the snapshot it resolved to. The tarball of its
+    /// nixpkgs commit is fetched if it is known, so the lock also work on other machines, and the
+    /// store path is used as is otherwise.
+    PinnedSystemWide {
+        package: String,
+        store_path: String,
+        /// the url of the tarball of `revision`
+        #[serde(default = "Option::default")]
+        url: Option<String>,
+        /// the sha256 of the NAR serialisation of the content of `url`, or of the store path if
+        /// there is no url (in the nix base32 format)
+        sha256: String,
+        /// the nixpkgs commit of this snapshot, read from the `.git-revision` of the channel
+        revision: Option<String>,
+        /// the version of this snapshot, read from the `.version` of the channel
+        version: Option<String>,
+    },
+    /// A flake, locked to a fully resolved reference
+    Flake {
+        /// the locked reference, that include the narHash
+        locked: String,
+        /// the narHash of the flake, in the SRI format
+        nar_hash: String,
+        output: Option<String>,
+    },
 }
 
 impl FixedInput {
     pub fn generate_nix_fetch(&self) -> String {
         match self {
+            // the rev may not be reachable from the default branch (like a commit of another
+            // branch or a tag), so all the refs of the repository are fetched
+            Self::Git { url, rev, .. } => format!(
+                "(builtins.fetchGit {{ url = {}; rev = {}; allRefs = true; }})",
+                escape_string(url),
+                escape_string(rev)
+            ),
+            Self::Tarball { url, sha256 } => format!(
+                "(builtins.fetchTarball {{ url = {}; sha256 = {}; }})",
+                escape_string(url),
+                escape_string(sha256)
+            ),
+            Self::File { url, sha256 } => format!(
+                "(builtins.fetchurl {{ url = {}; sha256 = {}; }})",
+                escape_string(url),
+                escape_string(sha256)
+            ),
+            Self::Path { path, sha256, name } => format!(
+                "(builtins.path {{ path = /. + {}; sha256 = {}; name = {}; }})",
+                escape_string(path),
+                escape_string(sha256),
+                escape_string(name)
+            ),
             Self::LocalPath(absolute_path) => {
                 format!("(builtins.toPath {})", escape_string(absolute_path))
             }
             Self::SystemWide(library) => format!("<{}>", library),
+            Self::PinnedSystemWide {
+                url: Some(url),
+                sha256,
+                ..
+            } => format!(
+                "(builtins.fetchTarball {{ url = {}; sha256 = {}; }})",
+                escape_string(url),
+                escape_string(sha256)
+            ),
+            Self::PinnedSystemWide {
+                package,
+                store_path,
+                sha256,
+                ..
+            } => format!(
+                "(builtins.path {{ path = /. + {}; sha256 = {}; name = {}; }})",
+                escape_string(store_path),
+                escape_string(sha256),
+                escape_string(&store_path_name(Path::new(package)))
+            ),
+            Self::Flake { locked, output, .. } => {
+                let mut result = format!("(builtins.getFlake {})", escape_string(locked));
+                for attribute in output.iter().flat_map(|output| output.split('.')) {
+                    result.push('.');
+                    result.push_str(&escape_string(attribute));
+                }
+                result
+            }
+        }
+    }
+
+    /// true if the input is a nix value by itself, and should be used as is rather than imported
+    pub fn is_value(&self) -> bool {
+        matches!(self, Self::Flake { .. })
+    }
+
+    /// true if this input is locked to the current content of a local path. Such a lock can't
+    /// be restored once the content changed, as the old content is gone.
+    pub fn is_local_content(&self) -> bool {
+        match self {
+            Self::Path { .. } | Self::LocalPath(_) => true,
+            Self::Flake { locked, .. } => locked.starts_with("path:"),
+            _ => false,
         }
     }
+
+    /// A short human readable description of the version this input is locked to
+    pub fn revision(&self) -> String {
+        match self {
+            Self::Git { rev, .. } => rev.clone(),
+            Self::Tarball { sha256, .. }
+            | Self::File { sha256, .. }
+            | Self::Path { sha256, .. } => {
+                format!("sha256:{}", sha256)
+            }
+            Self::Flake { nar_hash, .. } => nar_hash.clone(),
+            Self::LocalPath(path) => path.clone(),
+            Self::SystemWide(library) => format!("<{}>", library),
+            Self::PinnedSystemWide {
+                revision,
+                version,
+                store_path,
+                ..
+            } => revision
+                .clone()
+                .or_else(|| version.clone())
+                .unwrap_or_else(|| store_path.clone()),
+        }
+    }
+
+    /// Lock a library of the nix search path to the channel snapshot at `store_path`: to the
+    /// tarball of its nixpkgs commit, downloaded from `archive_url`, or to the content of the store
+    /// path if the commit is unknown.
+    pub async fn pin_channel(
+        package: &str,
+        store_path: &Path,
+        archive_url: &str,
+    ) -> Result<Self, FetchInputError> {
+        let revision = read_channel_metadata(store_path, ".git-revision");
+        let (url, sha256) = match &revision {
+            Some(revision) => {
+                let url = format!("{}{}.tar.gz", archive_url, revision);
+                let sha256 = hash_tarball(&url).await?;
+                (Some(url), sha256)
+            }
+            None => (None, hash_local_path(store_path).await?),
+        };
+        Ok(Self::PinnedSystemWide {
+            package: package.to_string(),
+            store_path: store_path.to_string_lossy().to_string(),
+            url,
+            sha256,
+            revision,
+            version: read_channel_metadata(store_path, ".version"),
+        })
+    }
+
+    /// check if the content pointed by this input still match the lock.
+    pub async fn lock_state(&self) -> Result<LockState, FetchInputError> {
+        Ok(match self {
+            Self::Path { path, sha256, .. } => {
+                if &hash_local_path(Path::new(path)).await? == sha256 {
+                    LockState::UpToDate
+                } else {
+                    LockState::Stale
+                }
+            }
+            Self::LocalPath(_) => LockState::Stale,
+            // a path flake have no revision, so its content is checked like a local path
+            Self::Flake {
+                locked, nar_hash, ..
+            } if self.is_local_content() => {
+                let locked_flake =
+                    lock_flake(locked)
+                        .await
+                        .map_err(|err| FetchInputError::Flake {
+                            reference: locked.clone(),
+                            err,
+                        })?;
+                if &locked_flake.nar_hash == nar_hash {
+                    LockState::UpToDate
+                } else {
+                    LockState::Stale
+                }
+            }
+            _ => LockState::UpToDate,
+        })
+    }
+}
+
+#[test]
+fn test_git_input() {
+    let repository = crate::git::create_test_repository("git-input");
+    let url = format!("file://{}", repository.to_string_lossy());
+    let input: UpdatableInput =
+        serde_json::from_str(&format!(r#"{{"type": "Git", "url": "{}"}}"#, url)).unwrap();
+    let fixed = async_std::task::block_on(input.get_latest()).unwrap();
+    if let FixedInput::Git { rev, .. } = &fixed {
+        assert_eq!(
+            fixed.generate_nix_fetch(),
+            format!(
+                "(builtins.fetchGit {{ url = \"{}\"; rev = \"{}\"; allRefs = true; }})",
+                url, rev
+            )
+        );
+    } else {
+        panic!("expected a git fixed input, got {:?}", fixed);
+    };
+    std::fs::remove_dir_all(repository).unwrap();
+}
+
+#[test]
+fn test_url_input() {
+    let tarball = crate::download::create_test_tarball();
+    let fetch = |unpack: bool| {
+        let input = UpdatableInput::Url {
+            url: crate::download::serve_once(tarball.clone()),
+            unpack,
+        };
+        async_std::task::block_on(input.get_latest()).unwrap()
+    };
+    if let FixedInput::File { sha256, .. } = fetch(false) {
+        assert_eq!(sha256, to_nix_base32(&flat_sha256(&tarball)));
+    } else {
+        panic!("expected a file fixed input");
+    };
+    let destination = TempDir::new("url-input-test").unwrap();
+    let root = unpack_tarball(&tarball, destination.path()).unwrap();
+    if let FixedInput::Tarball { sha256, url } = fetch(true) {
+        assert_eq!(sha256, to_nix_base32(&nar_sha256(&root).unwrap()));
+        assert!(FixedInput::Tarball {
+            url,
+            sha256: sha256.clone()
+        }
+        .generate_nix_fetch()
+        .contains(&format!("sha256 = \"{}\";", sha256)));
+    } else {
+        panic!("expected a tarball fixed input");
+    };
+}
+
+#[test]
+fn test_local_path_input() {
+    let directory = TempDir::new("local-path-input-test").unwrap();
+    let package = directory.path().join("my package");
+    std::fs::create_dir(&package).unwrap();
+    std::fs::write(package.join("default.nix"), "{ }: 1").unwrap();
+    let mut input = UpdatableInput::LocalPath {
+        path: PathBuf::from("my package"),
+        is_absolute: false,
+    };
+    input.ensure_path_is_absolute(directory.path());
+    async_std::task::block_on(async {
+        let fixed = input.get_latest().await.unwrap();
+        assert_eq!(
+            fixed.generate_nix_fetch(),
+            format!(
+                "(builtins.path {{ path = /. + \"{}\"; sha256 = \"{}\"; name = \"my_package\"; }})",
+                package.to_string_lossy(),
+                to_nix_base32(&nar_sha256(&package).unwrap())
+            )
+        );
+        assert_eq!(fixed.lock_state().await.unwrap(), LockState::UpToDate);
+        std::fs::write(package.join("default.nix"), "{ }: 2").unwrap();
+        assert_eq!(fixed.lock_state().await.unwrap(), LockState::Stale);
+    });
+}
+
+#[test]
+fn test_relative_base_dir() {
+    let mut input = UpdatableInput::LocalPath {
+        path: PathBuf::from("package.nix"),
+        is_absolute: false,
+    };
+    input.ensure_path_is_absolute(Path::new("./test_config/factorio"));
+    if let UpdatableInput::LocalPath { path, .. } = &input {
+        assert!(path.is_absolute());
+        assert_eq!(
+            path,
+            &std::env::current_dir()
+                .unwrap()
+                .join("test_config/factorio/package.nix")
+        );
+    } else {
+        panic!("expected a local path input, got {:?}", input);
+    };
+}
+
+#[test]
+fn test_pin_channel() {
+    // system wide inputs are pinned unless they are explicitly floating
+    let input: UpdatableInput =
+        serde_json::from_str(r#"{"type": "SystemWide", "package": "nixpkgs"}"#).unwrap();
+    assert_eq!(
+        input,
+        UpdatableInput::SystemWide {
+            package: "nixpkgs".to_string(),
+            floating: false
+        }
+    );
+
+    let channel = TempDir::new("channel-test").unwrap();
+    std::fs::write(channel.path().join("default.nix"), "{ }: 1").unwrap();
+    std::fs::write(channel.path().join(".version"), "20.09").unwrap();
+
+    // with a known commit, its tarball is fetched rather than the local store path
+    std::fs::write(channel.path().join(".git-revision"), "0123abcd\n").unwrap();
+    let tarball = crate::download::create_test_tarball();
+    let archive_url = format!("{}/", crate::download::serve_once(tarball.clone()));
+    let pinned = async_std::task::block_on(FixedInput::pin_channel(
+        "nixpkgs",
+        channel.path(),
+        &archive_url,
+    ))
+    .unwrap();
+    if let FixedInput::PinnedSystemWide { version, .. } = &pinned {
+        assert_eq!(version.as_deref(), Some("20.09"));
+    };
+    assert_eq!(pinned.revision(), "0123abcd");
+    let destination = TempDir::new("pin-channel-test").unwrap();
+    let root = unpack_tarball(&tarball, destination.path()).unwrap();
+    assert_eq!(
+        pinned.generate_nix_fetch(),
+        format!(
+            "(builtins.fetchTarball {{ url = \"{}0123abcd.tar.gz\"; sha256 = \"{}\"; }})",
+            archive_url,
+            to_nix_base32(&nar_sha256(&root).unwrap())
+        )
+    );
+
+    // otherwise, the store path is used as is
+    std::fs::remove_file(channel.path().join(".git-revision")).unwrap();
+    let pinned = async_std::task::block_on(FixedInput::pin_channel(
+        "nixpkgs",
+        channel.path(),
+        "http://unused/",
+    ))
+    .unwrap();
+    assert_eq!(pinned.revision(), "20.09");
+    assert_eq!(
+        pinned.generate_nix_fetch(),
+        format!(
+            "(builtins.path {{ path = /. + \"{}\"; sha256 = \"{}\"; name = \"nixpkgs\"; }})",
+            channel.path().to_string_lossy(),
+            to_nix_base32(&nar_sha256(channel.path()).unwrap())
+        )
+    );
+}
+
+#[test]
+fn test_flake_input() {
+    let directory = TempDir::new("flake-input-test").unwrap();
+    let flake = directory.path().join("flake");
+    std::fs::create_dir(&flake).unwrap();
+    std::fs::write(flake.join("flake.nix"), "{ outputs = { self }: { }; }").unwrap();
+    let mut input = UpdatableInput::Flake {
+        reference: "path:flake".to_string(),
+        output: Some("lib.my-function".to_string()),
+    };
+    input.ensure_path_is_absolute(directory.path());
+    async_std::task::block_on(async {
+        let fixed = input.get_latest().await.unwrap();
+        assert!(fixed.is_value());
+        if let FixedInput::Flake { locked, .. } = &fixed {
+            assert!(
+                locked.starts_with(&format!("path:{}?narHash=sha256-", flake.to_string_lossy()))
+            );
+            assert_eq!(
+                fixed.generate_nix_fetch(),
+                format!("(builtins.getFlake \"{}\").\"lib\".\"my-function\"", locked)
+            );
+        } else {
+            panic!("expected a flake fixed input, got {:?}", fixed);
+        };
+        assert_eq!(fixed.lock_state().await.unwrap(), LockState::UpToDate);
+        std::fs::write(flake.join("flake.nix"), "{ outputs = _: { }; }").unwrap();
+        assert_eq!(fixed.lock_state().await.unwrap(), LockState::Stale);
+    });
 }
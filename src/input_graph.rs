@@ -0,0 +1,174 @@
+//! The graph of the resolved inputs, with the config sources that use them, to understand where
+//! each input come from.
+use crate::cached_fixed_input::CachedFixedInput;
+use crate::input::{FixedInput, UpdatableInput};
+use crate::inputs_set::InputsSet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The format a graph can be rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GraphInput {
+    /// the name of the input in the generated nix file
+    pub name: String,
+    pub distant: UpdatableInput,
+    /// the version it is locked to, if it is already locked
+    pub locked: Option<FixedInput>,
+    /// the inputs passed to this input, by argument name
+    pub dependancies: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InputGraph {
+    pub inputs: Vec<GraphInput>,
+    /// the inputs used by each config source: config source id -> input key -> input name
+    pub config_sources: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Escape a string to be put in a quoted DOT identifier
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl InputGraph {
+    pub fn new(
+        inputs_set: &InputsSet,
+        link_to_name: &BTreeMap<String, BTreeMap<String, String>>,
+        lock: &CachedFixedInput,
+    ) -> Self {
+        Self {
+            inputs: inputs_set
+                .dependancies
+                .iter()
+                .map(|loaded| GraphInput {
+                    name: loaded.name.clone(),
+                    distant: loaded.distant.clone(),
+                    locked: lock.get(&loaded.distant).cloned(),
+                    dependancies: loaded
+                        .dependancies
+                        .iter()
+                        .map(|(argument, id)| (argument.clone(), inputs_set.get_name(*id)))
+                        .collect(),
+                })
+                .collect(),
+            config_sources: link_to_name.clone(),
+        }
+    }
+
+    /// render the graph in the Graphviz DOT format. Inputs are boxes, config sources are ellipses,
+    /// and edges are labeled with the name the input is known as.
+    pub fn to_dot(&self) -> String {
+        let mut result = String::from("digraph inputs {\n");
+        for input in &self.inputs {
+            let locked = input
+                .locked
+                .as_ref()
+                .map(|fixed| format!("locked to {}", fixed.revision()))
+                .unwrap_or_else(|| "not locked".to_string());
+            writeln!(
+                result,
+                "  \"input:{}\" [shape=box, label=\"{}\\n{}\\n{}\"];",
+                escape_dot(&input.name),
+                escape_dot(&input.name),
+                escape_dot(&input.distant.describe()),
+                escape_dot(&locked)
+            )
+            .unwrap();
+            for (argument, dependancy) in &input.dependancies {
+                writeln!(
+                    result,
+                    "  \"input:{}\" -> \"input:{}\" [label=\"{}\"];",
+                    escape_dot(&input.name),
+                    escape_dot(dependancy),
+                    escape_dot(argument)
+                )
+                .unwrap();
+            }
+        }
+        for (config_source, links) in &self.config_sources {
+            writeln!(
+                result,
+                "  \"source:{}\" [shape=ellipse, label=\"{}\"];",
+                escape_dot(config_source),
+                escape_dot(config_source)
+            )
+            .unwrap();
+            for (key, name) in links {
+                writeln!(
+                    result,
+                    "  \"source:{}\" -> \"input:{}\" [label=\"{}\"];",
+                    escape_dot(config_source),
+                    escape_dot(name),
+                    escape_dot(key)
+                )
+                .unwrap();
+            }
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+}
+
+#[test]
+fn test_input_graph() {
+    use crate::cached_fixed_input::UpdatePolicy;
+    use crate::inputs_set::InputDeclaration;
+    let declaration = |package: &str, depend_on: &[&str]| InputDeclaration {
+        distant: UpdatableInput::SystemWide {
+            package: package.to_string(),
+            floating: true,
+        },
+        depend_on: depend_on.iter().map(|x| x.to_string()).collect(),
+        update_policy: UpdatePolicy::Never,
+        export: false,
+        import: crate::import_kind::ImportKind::default(),
+    };
+    let mut group = BTreeMap::new();
+    group.insert("pkgs".to_string(), declaration("nixpkgs", &[]));
+    group.insert("mods".to_string(), declaration("mods", &["pkgs"]));
+    let mut groups = BTreeMap::new();
+    groups.insert("factorio".to_string(), group);
+    let mut inputs_set = InputsSet::new();
+    let link_to_name = inputs_set.add_groups(groups).unwrap();
+    let mut lock = CachedFixedInput::new();
+    lock.insert(
+        inputs_set.dependancies[0].distant.clone(),
+        FixedInput::SystemWide("nixpkgs".to_string()),
+    );
+
+    let pkgs = &link_to_name["factorio"]["pkgs"];
+    let mods = &link_to_name["factorio"]["mods"];
+
+    let graph = InputGraph::new(&inputs_set, &link_to_name, &lock);
+    let dot = graph.to_dot();
+    assert!(dot.contains(&format!(
+        "\"input:{}\" -> \"input:{}\" [label=\"pkgs\"];",
+        mods, pkgs
+    )));
+    assert!(dot.contains(&format!(
+        "\"source:factorio\" -> \"input:{}\" [label=\"mods\"];",
+        mods
+    )));
+    assert!(dot.contains("locked to <nixpkgs>"));
+    let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+    assert_eq!(json["config_sources"]["factorio"]["pkgs"], pkgs.as_str());
+    assert_eq!(json["inputs"][1]["dependancies"]["pkgs"], pkgs.as_str());
+}
@@ -0,0 +1,78 @@
+use crate::input::UpdatableInput;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A user defined rule that replace an input by another one in every config source, like
+/// `--override-input` of flakes. For example, to use a local checkout of nixpkgs instead of the
+/// system wide one.
+#[derive(Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct InputOverride {
+    /// the input to replace
+    pub from: UpdatableInput,
+    /// the input to use instead
+    pub to: UpdatableInput,
+}
+
+impl InputOverride {
+    /// check if this rule apply to `input`. A rule on a system wide input apply whether it is
+    /// floating or not.
+    pub fn matches(&self, input: &UpdatableInput) -> bool {
+        match (&self.from, input) {
+            (
+                UpdatableInput::SystemWide { package, .. },
+                UpdatableInput::SystemWide {
+                    package: input_package,
+                    ..
+                },
+            ) => package == input_package,
+            (from, input) => from == input,
+        }
+    }
+
+    /// make the path of the replacement absolute, relative to `base_dir` (the folder of the user
+    /// configuration)
+    pub fn ensure_path_is_absolute(&mut self, base_dir: &Path) {
+        self.from.ensure_path_is_absolute(base_dir);
+        self.to.ensure_path_is_absolute(base_dir);
+    }
+}
+
+/// return the input that should be used in place of `input`, according to the first matching rule
+pub fn apply_overrides<'a>(
+    overrides: &'a [InputOverride],
+    input: &'a UpdatableInput,
+) -> &'a UpdatableInput {
+    overrides
+        .iter()
+        .find(|rule| rule.matches(input))
+        .map(|rule| &rule.to)
+        .unwrap_or(input)
+}
+
+#[test]
+fn test_apply_overrides() {
+    let system_wide = |floating: bool| UpdatableInput::SystemWide {
+        package: "nixpkgs".to_string(),
+        floating,
+    };
+    let mut rule: InputOverride = serde_json::from_str(
+        r#"{
+            "from": {"type": "SystemWide", "package": "nixpkgs"},
+            "to": {"type": "LocalPath", "path": "nixpkgs"}
+        }"#,
+    )
+    .unwrap();
+    rule.ensure_path_is_absolute(Path::new("/home/me"));
+    let local = UpdatableInput::LocalPath {
+        path: "/home/me/nixpkgs".into(),
+        is_absolute: true,
+    };
+    let overrides = vec![rule];
+    assert_eq!(apply_overrides(&overrides, &system_wide(false)), &local);
+    assert_eq!(apply_overrides(&overrides, &system_wide(true)), &local);
+    let other = UpdatableInput::SystemWide {
+        package: "other".to_string(),
+        floating: false,
+    };
+    assert_eq!(apply_overrides(&overrides, &other), &other);
+}
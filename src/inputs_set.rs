@@ -1,91 +1,720 @@
+use crate::cached_fixed_input::UpdatePolicy;
+use crate::import_kind::ImportKind;
 use crate::input::UpdatableInput;
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-const SOURCE_PREFIX: &str = "source_";
+/// the name of each input in the generated nix file: config source id -> input key -> name
+pub type LinkToName = BTreeMap<String, BTreeMap<String, String>>;
+
+/// separate the config source id and the input key in a qualified input name, like `base:pkgs`
+const QUALIFIED_SEPARATOR: char = ':';
+
+/// the name a dependancy is passed to the input with: its key, without the config source
+fn argument_name(dependancy: &str) -> &str {
+    split_qualified(dependancy)
+        .map(|(_, key)| key)
+        .unwrap_or(dependancy)
+}
+
+/// names that can't be used for an input, as they would shadow what the generated nix file use
+const RESERVED_NAMES: &[&str] = &[
+    "assert", "builtins", "else", "false", "if", "import", "in", "inherit", "let", "null", "or",
+    "rec", "then", "true", "with",
+];
 
 #[derive(Hash, PartialEq, Eq, Clone, Deserialize, Debug)]
 pub struct InputDeclaration {
     pub distant: UpdatableInput,
     #[serde(default = "Vec::default")]
+    /// the inputs this input depend on. Inputs of other config sources are referenced with a
+    /// qualified name, like `base:pkgs`.
     pub depend_on: Vec<String>,
+    /// when the locked version of this input should be updated
+    #[serde(default = "UpdatePolicy::default")]
+    pub update_policy: UpdatePolicy,
+    /// if true, other config sources can depend on this input as `<config source id>:<key>`
+    #[serde(default = "bool::default")]
+    pub export: bool,
+    /// how the input is turned into a nix value, once fetched
+    #[serde(default = "ImportKind::default")]
+    pub import: ImportKind,
+}
+
+impl InputDeclaration {
+    /// the config sources whose exported inputs this input depend on
+    pub fn required_config_sources(&self) -> impl Iterator<Item = &str> {
+        self.depend_on
+            .iter()
+            .filter_map(|dependancy| split_qualified(dependancy))
+            .map(|(config_source, _)| config_source)
+    }
+}
+
+/// split a qualified input name in its config source id and input key, or return None if it
+/// isn't qualified
+pub fn split_qualified(dependancy: &str) -> Option<(&str, &str)> {
+    dependancy.split_once(QUALIFIED_SEPARATOR)
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InputsSetError {
+        UnknownDependancy { config_source: String, input: String, dependancy: String } {
+            display(me) -> ("the input {:?} of the config source {:?} depend on {:?}, which isn't an input of this config source", input, config_source, dependancy)
+        }
+        Cycle { config_source: String, path: Vec<String> } {
+            display(me) -> ("the inputs of the config source {:?} depend on each other: {}", config_source, path.join(" -> "))
+        }
+        UnknownExport { config_source: String, input: String, dependancy: String } {
+            display(me) -> ("the input {:?} of the config source {:?} depend on {:?}, but no enabled config source export it", input, config_source, dependancy)
+        }
+        UnknownPackageSet { config_source: String, input: String, package_set: String } {
+            display(me) -> ("the input {:?} of the config source {:?} use the callPackage of {:?}, which isn't one of its dependancies", input, config_source, package_set)
+        }
+        ArgumentClash { config_source: String, input: String, argument: String, dependancies: Vec<String> } {
+            display(me) -> ("the input {:?} of the config source {:?} depend on {}, which would all be passed as the argument {:?}", input, config_source, dependancies.join(" and "), argument)
+        }
+        ConfigSourceCycle { config_sources: Vec<String> } {
+            display(me) -> ("the config sources {} depend on the inputs of each other", config_sources.join(", "))
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum VisitState {
+    InProgress,
+    Done,
+}
+
+/// return the keys of `group` ordered so every input come after the inputs it depend on
+fn sort_by_dependancies(
+    config_source: &str,
+    group: &BTreeMap<String, InputDeclaration>,
+) -> Result<Vec<String>, InputsSetError> {
+    fn visit(
+        config_source: &str,
+        group: &BTreeMap<String, InputDeclaration>,
+        key: &str,
+        states: &mut BTreeMap<String, VisitState>,
+        path: &mut Vec<String>,
+        sorted: &mut Vec<String>,
+    ) -> Result<(), InputsSetError> {
+        match states.get(key) {
+            Some(VisitState::Done) => return Ok(()),
+            Some(VisitState::InProgress) => {
+                let start = path.iter().position(|k| k == key).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(key.to_string());
+                return Err(InputsSetError::Cycle {
+                    config_source: config_source.to_string(),
+                    path: cycle,
+                });
+            }
+            None => (),
+        };
+        states.insert(key.to_string(), VisitState::InProgress);
+        path.push(key.to_string());
+        // the inputs of other config sources are already loaded
+        for dependancy in group[key]
+            .depend_on
+            .iter()
+            .filter(|dependancy| split_qualified(dependancy).is_none())
+        {
+            if !group.contains_key(dependancy) {
+                return Err(InputsSetError::UnknownDependancy {
+                    config_source: config_source.to_string(),
+                    input: key.to_string(),
+                    dependancy: dependancy.clone(),
+                });
+            };
+            visit(config_source, group, dependancy, states, path, sorted)?;
+        }
+        path.pop();
+        states.insert(key.to_string(), VisitState::Done);
+        sorted.push(key.to_string());
+        Ok(())
+    }
+
+    let mut states = BTreeMap::new();
+    let mut sorted = Vec::new();
+    for key in group.keys() {
+        visit(
+            config_source,
+            group,
+            key,
+            &mut states,
+            &mut Vec::new(),
+            &mut sorted,
+        )?;
+    }
+    Ok(sorted)
 }
 
 #[derive(PartialOrd, PartialEq, Eq, Ord, Debug)]
 pub struct InputLoaded {
     pub distant: UpdatableInput,
     pub dependancies: BTreeMap<String, usize>,
+    pub import: ImportKind,
+    /// the config source id and key of every declaration of this input
+    pub declared_as: BTreeSet<(String, String)>,
+    /// the name of this input in the generated nix file
+    pub name: String,
+}
+
+/// turn `text` into a valid nix identifier, or None if nothing usable is left
+fn sanitize_name(text: &str) -> Option<String> {
+    let mut name: String = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    name = name.trim_matches(['_', '-']).to_string();
+    if name.is_empty() {
+        return None;
+    };
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    };
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        name.push('_');
+    };
+    Some(name)
+}
+
+/// a short name describing where the input come from, like `nixpkgs` for `<nixpkgs>` or
+/// `https://github.com/NixOS/nixpkgs.git`
+fn name_from_distant(distant: &UpdatableInput) -> Option<String> {
+    let last_segment = |text: &str| {
+        text.trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+    let name = match distant {
+        UpdatableInput::Git { url, .. } => last_segment(url).trim_end_matches(".git").to_string(),
+        UpdatableInput::Url { url, .. } => {
+            let file_name = last_segment(url.split(['?', '#']).next().unwrap());
+            file_name.split('.').next().unwrap_or_default().to_string()
+        }
+        UpdatableInput::LocalPath { path, .. } => path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        UpdatableInput::SystemWide { package, .. } => last_segment(package),
+        UpdatableInput::Flake { reference, .. } => {
+            last_segment(reference.split('?').next().unwrap())
+        }
+    };
+    sanitize_name(&name)
 }
 
 #[derive(Default)]
 pub struct InputsSet {
     pub dependancies: Vec<InputLoaded>,
+    /// the position of the exported inputs, by their qualified name
+    exports: BTreeMap<String, usize>,
+    /// the most eager update policy of the declarations of each input
+    pub update_policies: BTreeMap<UpdatableInput, UpdatePolicy>,
 }
 
-//TODO: an id to name that can help do more human readible format (ensure they stay unique even if new data are added afterward)
 impl InputsSet {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// add the inputs of several config sources, each after the config sources whose inputs it
+    /// depend on, and return the name of each input in the generated nix file, by config source
+    pub fn add_groups(
+        &mut self,
+        mut groups: BTreeMap<String, BTreeMap<String, InputDeclaration>>,
+    ) -> Result<LinkToName, InputsSetError> {
+        let mut positions = BTreeMap::new();
+        while !groups.is_empty() {
+            let ready: Vec<String> = groups
+                .iter()
+                .filter(|(config_source, group)| {
+                    group
+                        .values()
+                        .flat_map(InputDeclaration::required_config_sources)
+                        .all(|required| {
+                            required == config_source.as_str() || !groups.contains_key(required)
+                        })
+                })
+                .map(|(config_source, _)| config_source.clone())
+                .collect();
+            if ready.is_empty() {
+                return Err(InputsSetError::ConfigSourceCycle {
+                    config_sources: groups.keys().cloned().collect(),
+                });
+            };
+            for config_source in ready {
+                let group = groups.remove(&config_source).unwrap();
+                let group_positions = self.add_group_inputs(&config_source, group)?;
+                positions.insert(config_source, group_positions);
+            }
+        }
+        self.assign_names();
+        Ok(positions
+            .into_iter()
+            .map(|(config_source, group_positions)| {
+                (config_source, self.names_of(&group_positions))
+            })
+            .collect())
+    }
+
+    /// add the inputs of a config source, each after the inputs it depend on, and return the
+    /// name of each input in the generated nix file. The inputs of other config sources it depend
+    /// on should have been added before.
     pub fn add_group(
         &mut self,
+        config_source: &str,
+        group: BTreeMap<String, InputDeclaration>,
+    ) -> Result<BTreeMap<String, String>, InputsSetError> {
+        let positions = self.add_group_inputs(config_source, group)?;
+        self.assign_names();
+        Ok(self.names_of(&positions))
+    }
+
+    /// the name of the inputs at the given positions
+    fn names_of(&self, positions: &BTreeMap<String, usize>) -> BTreeMap<String, String> {
+        positions
+            .iter()
+            .map(|(key, position)| (key.clone(), self.get_name(*position)))
+            .collect()
+    }
+
+    /// add the inputs of a config source like `add_group`, and return their position. They are
+    /// named by `assign_names`.
+    fn add_group_inputs(
+        &mut self,
+        config_source: &str,
         mut group: BTreeMap<String, InputDeclaration>,
-    ) -> BTreeMap<String, String> {
-        let mut loaded_dependancies: BTreeMap<String, usize> = BTreeMap::new();
-        while !group.is_empty() {
-            //TODO: rewrite with drain_filter when stabilized
-            let mut to_remove: Vec<String> = Vec::new();
-            for (k, v) in group.iter() {
-                //do not try to add this if not all the dependancies are known
-                for dependancy_key in &v.depend_on {
-                    if !loaded_dependancies.contains_key(dependancy_key) {
-                        continue;
-                    };
+    ) -> Result<BTreeMap<String, usize>, InputsSetError> {
+        // dependancies on the inputs of the same config source don't need to be qualified
+        for declaration in group.values_mut() {
+            for dependancy in declaration.depend_on.iter_mut() {
+                if let Some((source, key)) = split_qualified(dependancy) {
+                    if source == config_source {
+                        *dependancy = key.to_string();
+                    }
                 }
-                loaded_dependancies.insert(k.to_string(), self.add_input(v, &loaded_dependancies));
-                to_remove.push(k.to_string());
             }
-            if to_remove.is_empty() {
-                panic!("(TODO: clearer error message) infinite recursion detected in add_group of InputsList")
+        }
+        let mut available: BTreeMap<String, usize> = BTreeMap::new();
+        for (key, declaration) in &group {
+            // a qualified dependancy is passed with its unqualified name, so it may clash with
+            // another dependancy
+            let mut by_argument: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+            for dependancy in &declaration.depend_on {
+                by_argument
+                    .entry(argument_name(dependancy))
+                    .or_default()
+                    .push(dependancy.clone());
+            }
+            if let Some((argument, dependancies)) = by_argument
+                .into_iter()
+                .find(|(_, dependancies)| dependancies.len() > 1)
+            {
+                return Err(InputsSetError::ArgumentClash {
+                    config_source: config_source.to_string(),
+                    input: key.clone(),
+                    argument: argument.to_string(),
+                    dependancies,
+                });
             };
-            for remove in &to_remove {
-                group.remove(remove).unwrap();
+            if let Some(package_set) = declaration.import.package_set() {
+                if !declaration
+                    .depend_on
+                    .iter()
+                    .any(|dependancy| argument_name(dependancy) == package_set)
+                {
+                    return Err(InputsSetError::UnknownPackageSet {
+                        config_source: config_source.to_string(),
+                        input: key.clone(),
+                        package_set: package_set.to_string(),
+                    });
+                }
+            };
+            for dependancy in &declaration.depend_on {
+                if split_qualified(dependancy).is_some() {
+                    let position = self.exports.get(dependancy).ok_or_else(|| {
+                        InputsSetError::UnknownExport {
+                            config_source: config_source.to_string(),
+                            input: key.clone(),
+                            dependancy: dependancy.clone(),
+                        }
+                    })?;
+                    available.insert(dependancy.clone(), *position);
+                }
             }
         }
-        loaded_dependancies
-            .iter()
-            .map(|(k, v)| (k, format!("{}{}", SOURCE_PREFIX, v)))
-            .fold(BTreeMap::new(), |mut map, (k, v)| {
-                map.insert(k.into(), v);
-                map
-            })
+        let mut loaded_dependancies: BTreeMap<String, usize> = BTreeMap::new();
+        for key in sort_by_dependancies(config_source, &group)? {
+            let declaration = &group[&key];
+            let position = self.add_input(config_source, &key, declaration, &available);
+            if declaration.export {
+                self.exports.insert(
+                    format!("{}{}{}", config_source, QUALIFIED_SEPARATOR, key),
+                    position,
+                );
+            };
+            available.insert(key.clone(), position);
+            loaded_dependancies.insert(key, position);
+        }
+        Ok(loaded_dependancies)
     }
 
+    /// add an input declared as `key` by `config_source`, and return its position. Inputs are
+    /// never moved once added, and the same input (with the same dependancies, imported the same
+    /// way) is only added once. `input_dependancies` contain the position of the inputs it can
+    /// depend on, with their name as written in `depend_on`. The input of other config sources
+    /// are passed to the input with their unqualified name. The input is named by `assign_names`.
     pub fn add_input(
         &mut self,
+        config_source: &str,
+        key: &str,
         input: &InputDeclaration,
         input_dependancies: &BTreeMap<String, usize>,
     ) -> usize {
         let mut dependancies = BTreeMap::new();
         for dep in &input.depend_on {
-            dependancies.insert(dep.to_string(), *input_dependancies.get(dep).unwrap());
+            dependancies.insert(
+                argument_name(dep).to_string(),
+                *input_dependancies.get(dep).unwrap(),
+            );
         }
-        let loaded = InputLoaded {
+
+        let policy = self
+            .update_policies
+            .entry(input.distant.clone())
+            .or_insert(input.update_policy);
+        *policy = policy.most_eager(input.update_policy);
+
+        let declared_as = (config_source.to_string(), key.to_string());
+        if let Some(position) = self.dependancies.iter().position(|loaded| {
+            loaded.distant == input.distant
+                && loaded.dependancies == dependancies
+                && loaded.import == input.import
+        }) {
+            self.dependancies[position].declared_as.insert(declared_as);
+            return position;
+        };
+        self.dependancies.push(InputLoaded {
             distant: input.distant.clone(),
             dependancies,
-        };
+            import: input.import.clone(),
+            declared_as: std::iter::once(declared_as).collect(),
+            name: String::new(),
+        });
+        self.dependancies.len() - 1
+    }
 
-        match self.dependancies.binary_search(&loaded) {
-            Ok(position) => position,
-            Err(new_position) => {
-                self.dependancies.insert(new_position, loaded);
-                new_position
-            }
+    /// name every input in the generated nix file. An input is named after its distant (or its
+    /// key if the distant give no name), like `nixpkgs`, unless another input would have the same
+    /// name, in which case it is named `<config source>_<key>` after its first declaration, like
+    /// `factorio_pkgs`. A number is only added if that name is still taken. The names only depend
+    /// on the inputs in the set, not on the order they were added in.
+    fn assign_names(&mut self) {
+        fn first_declaration(loaded: &InputLoaded) -> &(String, String) {
+            loaded.declared_as.iter().next().unwrap()
+        }
+        let short_names: Vec<String> = self
+            .dependancies
+            .iter()
+            .map(|loaded| {
+                name_from_distant(&loaded.distant)
+                    .or_else(|| sanitize_name(&first_declaration(loaded).1))
+                    .unwrap_or_else(|| "input".to_string())
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..self.dependancies.len()).collect();
+        order.sort_by_key(|position| first_declaration(&self.dependancies[*position]));
+        let mut used = BTreeSet::new();
+        for position in order {
+            let short_name = &short_names[position];
+            let preferred = if short_names
+                .iter()
+                .filter(|name| *name == short_name)
+                .count()
+                == 1
+            {
+                short_name.clone()
+            } else {
+                let (config_source, key) = first_declaration(&self.dependancies[position]);
+                sanitize_name(&format!("{}_{}", config_source, key))
+                    .unwrap_or_else(|| "input".to_string())
+            };
+            let name = std::iter::once(preferred.clone())
+                .chain((2..).map(|count| format!("{}_{}", preferred, count)))
+                .find(|name| !used.contains(name))
+                .unwrap();
+            used.insert(name.clone());
+            self.dependancies[position].name = name;
         }
     }
 
+    pub fn update_policy(&self, input: &UpdatableInput) -> UpdatePolicy {
+        self.update_policies.get(input).copied().unwrap_or_default()
+    }
+
     pub fn get_name(&self, id: usize) -> String {
-        format!("{}{}", SOURCE_PREFIX, id)
+        self.dependancies[id].name.clone()
+    }
+}
+
+#[test]
+fn test_add_group_order_and_errors() {
+    let declaration = |package: &str, depend_on: &[&str]| InputDeclaration {
+        distant: UpdatableInput::SystemWide {
+            package: package.to_string(),
+            floating: true,
+        },
+        depend_on: depend_on.iter().map(|x| x.to_string()).collect(),
+        update_policy: UpdatePolicy::Never,
+        export: false,
+        import: ImportKind::default(),
+    };
+    let group = |declarations: Vec<(&str, InputDeclaration)>| {
+        declarations
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    // "a" is sorted first, but depend on "b"
+    let ordered = group(vec![
+        ("a", declaration("a", &["b"])),
+        ("b", declaration("b", &[])),
+    ]);
+    assert_eq!(
+        sort_by_dependancies("source", &ordered).unwrap(),
+        vec!["b".to_string(), "a".to_string()]
+    );
+    assert!(InputsSet::new().add_group("source", ordered).is_ok());
+
+    let unknown = group(vec![("a", declaration("a", &["missing"]))]);
+    match InputsSet::new().add_group("source", unknown) {
+        Err(InputsSetError::UnknownDependancy { dependancy, .. }) => {
+            assert_eq!(dependancy, "missing")
+        }
+        other => panic!("expected an unknown dependancy error, got {:?}", other),
+    };
+
+    let cycle = group(vec![
+        ("a", declaration("a", &["b"])),
+        ("b", declaration("b", &["c"])),
+        ("c", declaration("c", &["a"])),
+    ]);
+    match InputsSet::new().add_group("source", cycle) {
+        Err(InputsSetError::Cycle { path, .. }) => assert_eq!(path, vec!["a", "b", "c", "a"]),
+        other => panic!("expected a cycle error, got {:?}", other),
+    };
+}
+
+#[test]
+fn test_input_names() {
+    let declaration = |distant: UpdatableInput| InputDeclaration {
+        distant,
+        depend_on: Vec::new(),
+        update_policy: UpdatePolicy::Never,
+        export: false,
+        import: ImportKind::default(),
+    };
+    let nixpkgs = || UpdatableInput::SystemWide {
+        package: "nixpkgs".to_string(),
+        floating: false,
+    };
+    let fork = || UpdatableInput::Git {
+        url: "https://example.com/me/nixpkgs.git".to_string(),
+        reference: "HEAD".to_string(),
+    };
+    let group = |key: &str, distant: UpdatableInput| {
+        let mut group = BTreeMap::new();
+        group.insert(key.to_string(), declaration(distant));
+        group
+    };
+
+    let mut inputs_set = InputsSet::new();
+    let names = inputs_set.add_group("game", group("pkgs", fork())).unwrap();
+    assert_eq!(names["pkgs"], "nixpkgs");
+    // two inputs with the same name are named after their config source and key
+    let mut other = InputsSet::new();
+    let mut groups = BTreeMap::new();
+    groups.insert("editor".to_string(), group("pkgs", nixpkgs()));
+    groups.insert("game".to_string(), group("pkgs", fork()));
+    groups.insert("other".to_string(), group("np", nixpkgs()));
+    let names = other.add_groups(groups.clone()).unwrap();
+    assert_eq!(names["editor"]["pkgs"], "editor_pkgs");
+    assert_eq!(names["game"]["pkgs"], "game_pkgs");
+    // the same input is only added once
+    assert_eq!(names["other"]["np"], "editor_pkgs");
+    assert_eq!(other.dependancies.len(), 2);
+    // the names don't depend on the order the inputs were added in
+    let mut reversed = InputsSet::new();
+    for (config_source, group) in groups.into_iter().rev() {
+        reversed.add_group(&config_source, group).unwrap();
     }
+    assert_eq!(reversed.get_name(0), "editor_pkgs");
+    assert_eq!(reversed.get_name(1), "game_pkgs");
+    // a number is only added when the name is still taken
+    let mut taken = InputsSet::new();
+    let mut groups = BTreeMap::new();
+    groups.insert("a".to_string(), group("b_c", nixpkgs()));
+    groups.insert("a_b".to_string(), group("c", fork()));
+    let names = taken.add_groups(groups).unwrap();
+    assert_eq!(names["a"]["b_c"], "a_b_c");
+    assert_eq!(names["a_b"]["c"], "a_b_c_2");
+
+    assert_eq!(sanitize_name("3d-game"), Some("_3d-game".to_string()));
+    assert_eq!(sanitize_name("import"), Some("import_".to_string()));
+    assert_eq!(sanitize_name("./"), None);
+    assert_eq!(
+        name_from_distant(&UpdatableInput::Url {
+            url: "https://example.com/archive/v1.2.tar.gz?x=1".to_string(),
+            unpack: true
+        }),
+        Some("v1".to_string())
+    );
+}
+
+#[test]
+fn test_exported_inputs() {
+    let declaration = |package: &str, depend_on: &[&str], export: bool| InputDeclaration {
+        distant: UpdatableInput::SystemWide {
+            package: package.to_string(),
+            floating: true,
+        },
+        depend_on: depend_on.iter().map(|x| x.to_string()).collect(),
+        update_policy: UpdatePolicy::Never,
+        export,
+        import: ImportKind::default(),
+    };
+    let mut groups = BTreeMap::new();
+    let mut base = BTreeMap::new();
+    base.insert("pkgs".to_string(), declaration("nixpkgs", &[], true));
+    base.insert("private".to_string(), declaration("private", &[], false));
+    // "app" is sorted before "base", but depend on it
+    let mut app = BTreeMap::new();
+    app.insert(
+        "game".to_string(),
+        declaration("game", &["base:pkgs"], false),
+    );
+    groups.insert("base".to_string(), base);
+    groups.insert("app".to_string(), app.clone());
+
+    let mut inputs_set = InputsSet::new();
+    let names = inputs_set.add_groups(groups.clone()).unwrap();
+    let game = &inputs_set.dependancies[inputs_set
+        .dependancies
+        .iter()
+        .position(|loaded| loaded.name == names["app"]["game"])
+        .unwrap()];
+    assert_eq!(
+        inputs_set.get_name(game.dependancies["pkgs"]),
+        names["base"]["pkgs"]
+    );
+
+    let mut private = BTreeMap::new();
+    private.insert(
+        "game".to_string(),
+        declaration("game", &["base:private"], false),
+    );
+    groups.insert("app".to_string(), private);
+    assert!(matches!(
+        InputsSet::new().add_groups(groups.clone()),
+        Err(InputsSetError::UnknownExport { .. })
+    ));
+
+    let mut clash = BTreeMap::new();
+    clash.insert("pkgs".to_string(), declaration("local", &[], false));
+    clash.insert(
+        "game".to_string(),
+        declaration("game", &["base:pkgs", "pkgs"], false),
+    );
+    groups.insert("app".to_string(), clash);
+    match InputsSet::new().add_groups(groups.clone()) {
+        Err(InputsSetError::ArgumentClash {
+            config_source,
+            argument,
+            ..
+        }) => {
+            assert_eq!(config_source, "app");
+            assert_eq!(argument, "pkgs");
+        }
+        other => panic!("expected an argument clash error, got {:?}", other),
+    };
+
+    groups.remove("base");
+    groups.insert("app".to_string(), app);
+    assert!(matches!(
+        InputsSet::new().add_groups(groups),
+        Err(InputsSetError::UnknownExport { .. })
+    ));
+}
+
+#[test]
+fn test_call_package_need_its_package_set() {
+    let mut group = BTreeMap::new();
+    group.insert(
+        "game".to_string(),
+        InputDeclaration {
+            distant: UpdatableInput::SystemWide {
+                package: "game".to_string(),
+                floating: true,
+            },
+            depend_on: Vec::new(),
+            update_policy: UpdatePolicy::Never,
+            export: false,
+            import: ImportKind::CallPackage {
+                package_set: "pkgs".to_string(),
+                arguments: None,
+            },
+        },
+    );
+    assert!(matches!(
+        InputsSet::new().add_group("source", group),
+        Err(InputsSetError::UnknownPackageSet { .. })
+    ));
+}
+
+#[test]
+fn test_same_input_imported_differently() {
+    let nixpkgs = |arguments: Option<&str>| {
+        let mut group = BTreeMap::new();
+        group.insert(
+            "pkgs".to_string(),
+            InputDeclaration {
+                distant: UpdatableInput::SystemWide {
+                    package: "nixpkgs".to_string(),
+                    floating: true,
+                },
+                depend_on: Vec::new(),
+                update_policy: UpdatePolicy::Never,
+                export: false,
+                import: ImportKind::Function {
+                    arguments: arguments.map(str::to_string),
+                },
+            },
+        );
+        group
+    };
+    let mut groups = BTreeMap::new();
+    groups.insert(
+        "factorio".to_string(),
+        nixpkgs(Some("{ config.allowUnfree = true; }")),
+    );
+    groups.insert("minetest".to_string(), nixpkgs(None));
+    groups.insert("other".to_string(), nixpkgs(None));
+    let mut inputs_set = InputsSet::new();
+    let names = inputs_set.add_groups(groups).unwrap();
+    assert_eq!(inputs_set.dependancies.len(), 2);
+    assert_ne!(names["factorio"]["pkgs"], names["minetest"]["pkgs"]);
+    assert_eq!(names["minetest"]["pkgs"], names["other"]["pkgs"]);
 }
@@ -5,10 +5,23 @@ pub mod async_command;
 pub mod cached_fixed_input;
 pub mod config_manager;
 pub mod config_source;
+pub mod config_value;
+pub mod download;
+pub mod flake;
 pub mod gate;
+pub mod gate_expression;
+pub mod git;
 pub mod gui;
+pub mod import_kind;
 pub mod input;
+pub mod input_graph;
+pub mod input_override;
 pub mod inputs_set;
+pub mod lock_diff;
+pub mod lock_file;
+pub mod nar;
+pub mod nix_path;
 pub mod nixtool;
 pub mod ongoing_save;
 pub mod saved_config;
+pub mod temp_dir;
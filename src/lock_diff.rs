@@ -0,0 +1,194 @@
+use crate::cached_fixed_input::{CachedFixedInput, PruneReport};
+use crate::input::{FixedInput, UpdatableInput};
+use std::collections::BTreeMap;
+
+/// How the pin of an input changed between two locks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinChange {
+    Added(FixedInput),
+    Removed(FixedInput),
+    Changed { old: FixedInput, new: FixedInput },
+}
+
+impl PinChange {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Added(new) => format!("added ({})", new.revision()),
+            Self::Removed(old) => format!("removed (was {})", old.revision()),
+            Self::Changed { old, new } if !self.can_revert() => format!(
+                "{} → {} (the content changed, kept even if the update is rejected)",
+                old.revision(),
+                new.revision()
+            ),
+            Self::Changed { old, new } => format!("{} → {}", old.revision(), new.revision()),
+        }
+    }
+
+    /// false if the previous pin can't be used anymore: a local path whose content changed
+    pub fn can_revert(&self) -> bool {
+        !matches!(self, Self::Changed { new, .. } if new.is_local_content())
+    }
+}
+
+/// The difference between two version of the lock
+#[derive(Debug, Clone, Default)]
+pub struct LockDiff {
+    pub changes: BTreeMap<UpdatableInput, PinChange>,
+}
+
+impl LockDiff {
+    pub fn new(old: &CachedFixedInput, new: &CachedFixedInput) -> Self {
+        let mut changes = BTreeMap::new();
+        for (input, new_fixed) in new.iter() {
+            match old.get(input) {
+                None => {
+                    changes.insert(input.clone(), PinChange::Added(new_fixed.clone()));
+                }
+                Some(old_fixed) if old_fixed != new_fixed => {
+                    changes.insert(
+                        input.clone(),
+                        PinChange::Changed {
+                            old: old_fixed.clone(),
+                            new: new_fixed.clone(),
+                        },
+                    );
+                }
+                Some(_) => (),
+            }
+        }
+        for (input, old_fixed) in old.iter() {
+            if new.get(input).is_none() {
+                changes.insert(input.clone(), PinChange::Removed(old_fixed.clone()));
+            }
+        }
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// group the changes by the config source that use them. `inputs_by_source` map each config
+    /// source id to its inputs (by name). Changes of inputs used by no config source are listed
+    /// with a `None` config source.
+    pub fn per_config_source(
+        &self,
+        inputs_by_source: &BTreeMap<String, BTreeMap<String, UpdatableInput>>,
+    ) -> BTreeMap<Option<String>, Vec<(String, PinChange)>> {
+        let mut result: BTreeMap<Option<String>, Vec<(String, PinChange)>> = BTreeMap::new();
+        for (input, change) in &self.changes {
+            let mut used = false;
+            for (source, inputs) in inputs_by_source {
+                for (name, source_input) in inputs {
+                    if source_input == input {
+                        used = true;
+                        result
+                            .entry(Some(source.clone()))
+                            .or_default()
+                            .push((name.clone(), change.clone()));
+                    }
+                }
+            }
+            if !used {
+                result
+                    .entry(None)
+                    .or_default()
+                    .push((format!("{:?}", input), change.clone()));
+            }
+        }
+        result
+    }
+}
+
+/// A lock update waiting to be accepted or rejected by the user
+#[derive(Debug, Clone)]
+pub struct LockUpdateReview {
+    pub diff: LockDiff,
+    /// the lock with every update applied
+    pub updated: CachedFixedInput,
+    /// the unused inputs removed from the lock, and the ones kept until their grace period is over
+    pub pruned: PruneReport,
+}
+
+impl LockUpdateReview {
+    /// the lock to use if the update is rejected: the previous pins are kept, and only the inputs
+    /// that weren't locked before are added. Local paths whose content changed keep their new
+    /// pin, as the previous one would no longer match their content.
+    pub fn rejected(&self, previous: &CachedFixedInput) -> CachedFixedInput {
+        let mut result = self.updated.clone();
+        result.restore_from(previous, |input| {
+            self.diff
+                .changes
+                .get(input)
+                .is_none_or(PinChange::can_revert)
+        });
+        result
+    }
+}
+
+#[test]
+fn test_lock_diff() {
+    let input = |package: &str| UpdatableInput::SystemWide {
+        package: package.to_string(),
+        floating: true,
+    };
+    let fixed = |package: &str| FixedInput::SystemWide(package.to_string());
+    let mut old = CachedFixedInput::new();
+    old.insert(input("kept"), fixed("kept"));
+    old.insert(input("changed"), fixed("old"));
+    old.insert(input("removed"), fixed("removed"));
+    let mut new = CachedFixedInput::new();
+    new.insert(input("kept"), fixed("kept"));
+    new.insert(input("changed"), fixed("new"));
+    new.insert(input("added"), fixed("added"));
+
+    let diff = LockDiff::new(&old, &new);
+    assert_eq!(diff.changes.len(), 3);
+    assert_eq!(
+        diff.changes.get(&input("changed")),
+        Some(&PinChange::Changed {
+            old: fixed("old"),
+            new: fixed("new")
+        })
+    );
+    assert_eq!(
+        diff.changes.get(&input("removed")),
+        Some(&PinChange::Removed(fixed("removed")))
+    );
+
+    let mut inputs_by_source = BTreeMap::new();
+    inputs_by_source.insert(
+        "game".to_string(),
+        vec![("pkgs".to_string(), input("changed"))]
+            .into_iter()
+            .collect(),
+    );
+    let grouped = diff.per_config_source(&inputs_by_source);
+    assert_eq!(grouped.get(&Some("game".to_string())).unwrap().len(), 1);
+    assert_eq!(grouped.get(&None).unwrap().len(), 2);
+
+    let local = UpdatableInput::LocalPath {
+        path: "/src".into(),
+        is_absolute: true,
+    };
+    let local_fixed = |sha256: &str| FixedInput::Path {
+        path: "/src".to_string(),
+        sha256: sha256.to_string(),
+        name: "src".to_string(),
+    };
+    old.insert(local.clone(), local_fixed("old"));
+    new.insert(local.clone(), local_fixed("new"));
+    let diff = LockDiff::new(&old, &new);
+    assert!(!diff.changes[&local].can_revert());
+
+    let review = LockUpdateReview {
+        diff,
+        updated: new,
+        pruned: PruneReport::default(),
+    };
+    let rejected = review.rejected(&old);
+    assert_eq!(rejected.get(&local), Some(&local_fixed("new")));
+    assert_eq!(rejected.get(&input("changed")), Some(&fixed("old")));
+    assert_eq!(rejected.get(&input("removed")), Some(&fixed("removed")));
+    assert_eq!(rejected.get(&input("added")), Some(&fixed("added")));
+}
@@ -0,0 +1,216 @@
+//! The on-disk format of the lock file, and the migration from its older versions.
+use crate::cached_fixed_input::{now, LockedInput};
+use crate::input::{FixedInput, UpdatableInput};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The version of the lock file format written by this version of nixminigui. It is increased
+/// every time a locked input gain a new kind or field, and the older versions are migrated in
+/// [`LockFile::parse`].
+///
+/// - 1: first versioned format
+/// - 2: added the flake inputs, and the content hash of pinned system wide inputs
+pub const LOCK_FILE_VERSION: u64 = 2;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum LoadLockError {
+        CantReadFile { path: PathBuf, err: io::Error } {
+            cause(err)
+            display(me) -> ("can't read the lock file {:?}: {}", path, err)
+        }
+        CantParseFile { path: PathBuf, err: serde_json::error::Error } {
+            cause(err)
+            display(me) -> ("can't parse the lock file {:?}: {}", path, err)
+        }
+        MissingVersion { path: PathBuf } {
+            display(me) -> ("the lock file {:?} doesn't have a valid version", path)
+        }
+        UnsupportedVersion { path: PathBuf, version: u64 } {
+            display(me) -> ("the lock file {:?} use the version {} of the format, but this version of nixminigui only support up to the version {}", path, version, LOCK_FILE_VERSION)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockMetadata {
+    /// the version of nixminigui that wrote this lock file
+    pub nixminigui_version: String,
+    /// when this lock file was written, in seconds since the unix epoch
+    pub generated_at: u64,
+}
+
+impl LockMetadata {
+    pub fn new() -> Self {
+        Self {
+            nixminigui_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: now(),
+        }
+    }
+}
+
+impl Default for LockMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The content of a lock file, in its latest version
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockFile {
+    pub version: u64,
+    pub metadata: LockMetadata,
+    pub inputs: Vec<(UpdatableInput, LockedInput)>,
+}
+
+/// The lock file before it was versioned: a bare list of locked input
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UnversionedLockFile {
+    WithTime(Vec<(UpdatableInput, LockedInput)>),
+    /// written before the fetch time was recorded
+    WithoutTime(Vec<(UpdatableInput, FixedInput)>),
+}
+
+/// Remove from a list of locked inputs the system wide inputs that were pinned without the hash of
+/// their content, so they are pinned again.
+fn drop_unhashed_pins(inputs: &mut Value) {
+    if let Value::Array(inputs) = inputs {
+        inputs.retain(|input| {
+            let locked = &input[1];
+            let fixed = locked.get("fixed").unwrap_or(locked);
+            fixed
+                .get("PinnedSystemWide")
+                .is_none_or(|pin| pin.get("sha256").is_some())
+        });
+    };
+}
+
+impl LockFile {
+    pub fn new(inputs: Vec<(UpdatableInput, LockedInput)>) -> Self {
+        Self {
+            version: LOCK_FILE_VERSION,
+            metadata: LockMetadata::new(),
+            inputs,
+        }
+    }
+
+    /// Parse a lock file of any supported version, migrating it to the latest one
+    pub fn parse(content: &[u8], path: &Path) -> Result<Self, LoadLockError> {
+        let parse_error = |err| LoadLockError::CantParseFile {
+            path: path.to_path_buf(),
+            err,
+        };
+        let mut value: Value = serde_json::from_slice(content).map_err(parse_error)?;
+        // only the lock files written before the versioning (a bare list) have no version
+        let version = match &value {
+            Value::Array(_) => 0,
+            value => value
+                .get("version")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| LoadLockError::MissingVersion {
+                    path: path.to_path_buf(),
+                })?,
+        };
+        match version {
+            0 => {
+                drop_unhashed_pins(&mut value);
+                Ok(Self::migrate_from_unversioned(
+                    serde_json::from_value(value).map_err(parse_error)?,
+                ))
+            }
+            1 => {
+                if let Some(inputs) = value.get_mut("inputs") {
+                    drop_unhashed_pins(inputs);
+                };
+                value["version"] = LOCK_FILE_VERSION.into();
+                serde_json::from_value(value).map_err(parse_error)
+            }
+            LOCK_FILE_VERSION => serde_json::from_value(value).map_err(parse_error),
+            version => Err(LoadLockError::UnsupportedVersion {
+                path: path.to_path_buf(),
+                version,
+            }),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, LoadLockError> {
+        let content = std::fs::read(path).map_err(|err| LoadLockError::CantReadFile {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        Self::parse(&content, path)
+    }
+
+    fn migrate_from_unversioned(old: UnversionedLockFile) -> Self {
+        let inputs = match old {
+            UnversionedLockFile::WithTime(inputs) => inputs,
+            UnversionedLockFile::WithoutTime(inputs) => inputs
+                .into_iter()
+                .map(|(k, fixed)| {
+                    (
+                        k,
+                        LockedInput {
+                            fixed,
+                            fetched_at: None,
+                            unused_since: None,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        Self::new(inputs)
+    }
+}
+
+#[test]
+fn test_lock_file_migration() {
+    let path = Path::new("lockfile.json");
+    let unversioned = br#"[
+        [{"type": "SystemWide", "package": "nixpkgs"}, {"SystemWide": "nixpkgs"}],
+        [{"type": "LocalPath", "path": "/src", "is_absolute": true}, {"LocalPath": "/src"}]
+    ]"#;
+    let migrated = LockFile::parse(unversioned, path).unwrap();
+    assert_eq!(migrated.version, LOCK_FILE_VERSION);
+    assert_eq!(migrated.inputs.len(), 2);
+    assert_eq!(migrated.inputs[0].1.fetched_at, None);
+
+    let written = serde_json::to_vec(&migrated).unwrap();
+    let read_back = LockFile::parse(&written, path).unwrap();
+    assert_eq!(read_back.metadata, migrated.metadata);
+    assert_eq!(read_back.inputs[0].0, migrated.inputs[0].0);
+
+    let version_1 = br#"{
+        "version": 1,
+        "metadata": {"nixminigui_version": "0.1.0", "generated_at": 0},
+        "inputs": [
+            [
+                {"type": "SystemWide", "package": "nixpkgs", "floating": false},
+                {"fixed": {"PinnedSystemWide": {"package": "nixpkgs", "store_path": "/nix/store/x", "revision": null, "version": null}}, "fetched_at": 0}
+            ],
+            [
+                {"type": "SystemWide", "package": "nixpkgs", "floating": true},
+                {"fixed": {"SystemWide": "nixpkgs"}, "fetched_at": 0}
+            ]
+        ]
+    }"#;
+    let migrated = LockFile::parse(version_1, path).unwrap();
+    assert_eq!(migrated.version, LOCK_FILE_VERSION);
+    assert_eq!(migrated.inputs.len(), 1);
+    assert_eq!(
+        migrated.inputs[0].1.fixed,
+        FixedInput::SystemWide("nixpkgs".into())
+    );
+
+    match LockFile::parse(br#"{"inputs": []}"#, path) {
+        Err(LoadLockError::MissingVersion { .. }) => (),
+        other => panic!("expected a missing version error, got {:?}", other),
+    };
+    match LockFile::parse(br#"{"version": 99, "inputs": []}"#, path) {
+        Err(LoadLockError::UnsupportedVersion { version: 99, .. }) => (),
+        other => panic!("expected an unsupported version error, got {:?}", other),
+    };
+    assert!(LockFile::parse(b"{ not json", path).is_err());
+}
@@ -1,6 +1,7 @@
 use iced::{Application, Settings};
 use nixminigui::config_manager::ConfigManager;
 use nixminigui::gui::{Flags, NixMiniGuiApp};
+use nixminigui::input_graph::GraphFormat;
 use std::path::PathBuf;
 
 fn main() {
@@ -10,13 +11,36 @@ fn main() {
         PathBuf::from("./packages.nix"),
     );
     config_manager.load_config();
-    config_manager.load_lock();
-    config_manager
-        .add_configuration_source_from_path(PathBuf::from("./test_config/minetest"))
-        .unwrap();
-    config_manager
-        .add_configuration_source_from_path(PathBuf::from("./test_config/factorio"))
-        .unwrap();
+    if let Err(err) = config_manager.load_lock() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    };
+    for config_source in &["./test_config/minetest", "./test_config/factorio"] {
+        if let Err(err) =
+            config_manager.add_configuration_source_from_path(PathBuf::from(config_source))
+        {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        };
+    }
+
+    // `nixminigui graph [--json]` print the graph of the inputs of the enabled config sources
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("graph") {
+        let format = if args.iter().any(|arg| arg == "--json") {
+            GraphFormat::Json
+        } else {
+            GraphFormat::Dot
+        };
+        match config_manager.input_graph() {
+            Ok(graph) => print!("{}", graph.render(format)),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        return;
+    };
 
     let flags = Flags { config_manager };
 
@@ -0,0 +1,161 @@
+//! Native implementation of the nix archive (NAR) serialisation, used to compute the same content
+//! hash as nix does for paths added to the store.
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+const NIX_BASE32_ALPHABET: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn write_string<W: Write>(writer: &mut W, content: &[u8]) -> io::Result<()> {
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)?;
+    let padding = (8 - content.len() % 8) % 8;
+    writer.write_all(&[0; 8][..padding])
+}
+
+fn write_node<W: Write>(writer: &mut W, path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    write_string(writer, b"(")?;
+    write_string(writer, b"type")?;
+    if metadata.file_type().is_symlink() {
+        write_string(writer, b"symlink")?;
+        write_string(writer, b"target")?;
+        write_string(writer, fs::read_link(path)?.as_os_str().as_bytes())?;
+    } else if metadata.is_dir() {
+        write_string(writer, b"directory")?;
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        for name in entries {
+            write_string(writer, b"entry")?;
+            write_string(writer, b"(")?;
+            write_string(writer, b"name")?;
+            write_string(writer, name.as_bytes())?;
+            write_string(writer, b"node")?;
+            write_node(writer, &path.join(&name))?;
+            write_string(writer, b")")?;
+        }
+    } else {
+        write_string(writer, b"regular")?;
+        if metadata.permissions().mode() & 0o100 != 0 {
+            write_string(writer, b"executable")?;
+            write_string(writer, b"")?;
+        };
+        write_string(writer, b"contents")?;
+        write_string(writer, &fs::read(path)?)?;
+    };
+    write_string(writer, b")")
+}
+
+/// Serialize the file, directory or symlink at `path` as a NAR into `writer`
+pub fn write_nar<W: Write>(writer: &mut W, path: &Path) -> io::Result<()> {
+    write_string(writer, b"nix-archive-1")?;
+    write_node(writer, path)
+}
+
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compute the sha256 of the NAR serialisation of `path`
+pub fn nar_sha256(path: &Path) -> io::Result<[u8; 32]> {
+    let mut writer = HashWriter(Sha256::new());
+    write_nar(&mut writer, path)?;
+    Ok(writer.0.finalize().into())
+}
+
+/// Compute the sha256 of the given data
+pub fn flat_sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Encode a hash with the base32 variant used by nix
+pub fn to_nix_base32(hash: &[u8]) -> String {
+    let length = (hash.len() * 8 - 1) / 5 + 1;
+    let mut result = String::with_capacity(length);
+    for n in (0..length).rev() {
+        let bit = n * 5;
+        let i = bit / 8;
+        let j = bit % 8;
+        let mut c = (hash[i] as u16) >> j;
+        if i + 1 < hash.len() {
+            c |= (hash[i + 1] as u16) << (8 - j);
+        };
+        result.push(NIX_BASE32_ALPHABET[(c & 0x1f) as usize] as char);
+    }
+    result
+}
+
+/// Encode a sha256 hash in the SRI format (`sha256-<base64>`), as used by the `narHash` of flakes
+pub fn to_sri_sha256(hash: &[u8; 32]) -> String {
+    let mut result = String::from("sha256-");
+    for chunk in hash.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for n in 0..4 {
+            if n <= chunk.len() {
+                let index = (group >> (18 - 6 * n)) & 0x3f;
+                result.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                result.push('=');
+            };
+        }
+    }
+    result
+}
+
+#[test]
+fn test_to_nix_base32() {
+    assert_eq!(
+        to_nix_base32(&flat_sha256(b"")),
+        "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+    );
+    assert_eq!(
+        to_nix_base32(&flat_sha256(b"abc")),
+        "1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s"
+    );
+    assert_eq!(
+        to_sri_sha256(&flat_sha256(b"")),
+        "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+    );
+}
+
+#[test]
+fn test_write_nar() {
+    let directory = crate::temp_dir::TempDir::new("nar-test").unwrap();
+    let root = directory.path().join("source");
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::write(root.join("default.nix"), "{ }: 1").unwrap();
+    fs::write(root.join("run.sh"), "#!/bin/sh\n").unwrap();
+    fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink("default.nix", root.join("link")).unwrap();
+    fs::write(root.join("lib/a.nix"), "1").unwrap();
+    // the expected `nix-hash --type sha256 --base32` of this tree
+    assert_eq!(
+        to_nix_base32(&nar_sha256(&root).unwrap()),
+        "1dk4mgaz5pqps1c4r0n1niw8qigqkm9phzh54w5n0073lb3nmaqj"
+    );
+    fs::write(directory.path().join("hello"), "hello").unwrap();
+    assert_eq!(
+        to_nix_base32(&nar_sha256(&directory.path().join("hello")).unwrap()),
+        "0sg9f58l1jj88w6pdrfdpj5x9b1zrwszk84j81zvby36q9whhhqa"
+    );
+}
@@ -0,0 +1,92 @@
+//! Resolution of entries of the nix search path (like `<nixpkgs>`), mimicking what nix does.
+use std::path::{Path, PathBuf};
+
+/// The channel directories nix look in when the entry isn't found in `NIX_PATH`
+fn default_channel_directories() -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        result.push(PathBuf::from(home).join(".nix-defexpr/channels"));
+    };
+    result.push(PathBuf::from(
+        "/nix/var/nix/profiles/per-user/root/channels",
+    ));
+    result
+}
+
+/// Split a `NIX_PATH` like string in its entries. Url aren't supported, and are skipped.
+fn split_nix_path(nix_path: &str) -> Vec<&str> {
+    nix_path
+        .split(':')
+        .filter(|entry| !entry.is_empty())
+        .filter(|entry| !entry.starts_with("//"))
+        .filter(|entry| !entry.ends_with("=http") && !entry.ends_with("=https"))
+        .filter(|entry| *entry != "http" && *entry != "https")
+        .collect()
+}
+
+/// Find the path `<package>` would refer to, given the content of a `NIX_PATH` like variable.
+pub fn resolve_in_nix_path(package: &str, nix_path: &str) -> Option<PathBuf> {
+    for entry in split_nix_path(nix_path) {
+        let candidate = if let Some((prefix, path)) = entry.split_once('=') {
+            if package == prefix {
+                PathBuf::from(path)
+            } else if let Some(rest) = package.strip_prefix(&format!("{}/", prefix)) {
+                Path::new(path).join(rest)
+            } else {
+                continue;
+            }
+        } else {
+            Path::new(entry).join(package)
+        };
+        if candidate.exists() {
+            return Some(candidate);
+        };
+    }
+    None
+}
+
+/// Find the path `<package>` would refer to, using the `NIX_PATH` environment variable and the
+/// default channels, then follow the symlinks to the store path.
+pub fn resolve_system_wide(package: &str) -> Option<PathBuf> {
+    let nix_path = std::env::var("NIX_PATH").unwrap_or_default();
+    let found = resolve_in_nix_path(package, &nix_path).or_else(|| {
+        default_channel_directories()
+            .iter()
+            .map(|directory| directory.join(package))
+            .find(|candidate| candidate.exists())
+    })?;
+    found.canonicalize().ok()
+}
+
+/// Read an optional metadata file (like `.git-revision`) of a channel
+pub fn read_channel_metadata(channel: &Path, file_name: &str) -> Option<String> {
+    std::fs::read_to_string(channel.join(file_name))
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+}
+
+#[test]
+fn test_resolve_in_nix_path() {
+    let directory = crate::temp_dir::TempDir::new("nix-path-test").unwrap();
+    let channels = directory.path().join("channels");
+    std::fs::create_dir_all(channels.join("nixpkgs/lib")).unwrap();
+    let custom = directory.path().join("custom");
+    std::fs::create_dir_all(&custom).unwrap();
+    let nix_path = format!(
+        "foo=https://example.com/foo.tar.gz:mypkgs={}:{}",
+        custom.to_string_lossy(),
+        channels.to_string_lossy()
+    );
+    assert_eq!(
+        resolve_in_nix_path("nixpkgs", &nix_path),
+        Some(channels.join("nixpkgs"))
+    );
+    assert_eq!(
+        resolve_in_nix_path("nixpkgs/lib", &nix_path),
+        Some(channels.join("nixpkgs/lib"))
+    );
+    assert_eq!(resolve_in_nix_path("mypkgs", &nix_path), Some(custom));
+    assert_eq!(resolve_in_nix_path("foo", &nix_path), None);
+    assert_eq!(resolve_in_nix_path("missing", &nix_path), None);
+}
@@ -36,7 +36,7 @@ pub fn to_nix_vec(list: &[String]) -> String {
     format!(
         "[ {}]",
         list.iter().fold(String::new(), |mut result, to_add| {
-            result.push_str(&to_add);
+            result.push_str(to_add);
             result.push(' ');
             result
         })
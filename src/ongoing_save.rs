@@ -1,6 +1,9 @@
 use crate::async_command::AsyncCommand;
+use crate::cached_fixed_input::CachedFixedInput;
 use crate::config_manager::ConfigManager;
+use crate::input::LockState;
 use crate::inputs_set::InputsSet;
+use crate::lock_diff::{LockDiff, LockUpdateReview};
 
 use futures::stream::unfold;
 use futures::stream::BoxStream;
@@ -15,16 +18,34 @@ use async_std::process::Command;
 
 pub struct OngoingSave {
     config_manager: ConfigManager,
+    lock_reviewed: bool,
 }
 
 impl OngoingSave {
     pub fn new(config_manager: ConfigManager) -> Self {
-        Self { config_manager }
+        Self {
+            config_manager,
+            lock_reviewed: false,
+        }
+    }
+
+    /// Create a save that use the lock of the config manager as is, without updating it. Used
+    /// once the user reviewed the lock update.
+    pub fn new_with_reviewed_lock(config_manager: ConfigManager) -> Self {
+        Self {
+            config_manager,
+            lock_reviewed: true,
+        }
     }
 }
 
 struct OngoingSaveProgress {
     config_manager: ConfigManager,
+    /// the lock before this save started
+    previous_lock: CachedFixedInput,
+    lock_reviewed: bool,
+    /// what the user should know about once the save is finished
+    warnings: Vec<String>,
     kind: OngoingSaveProgressKind,
 }
 
@@ -46,6 +67,12 @@ enum OngoingSaveProgressKind {
 pub enum OngoingSaveProgressMessage {
     Done(String),
     Log(String, String),
+    /// the save was aborted due to an error
+    Failed(String),
+    /// the save stopped, waiting for the user to accept or reject the update of the lock
+    ReviewLockUpdate(LockUpdateReview),
+    /// the save is finished, with the lock that was written and what the user should know about
+    Finished(CachedFixedInput, Vec<String>),
 }
 
 impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
@@ -54,18 +81,28 @@ impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
     fn hash(&self, state: &mut H) {
         std::any::TypeId::of::<Self>().hash(state);
         self.config_manager.hash(state);
+        self.lock_reviewed.hash(state);
     }
 
     fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Self::Output> {
         Box::pin(unfold(
             OngoingSaveProgress {
+                previous_lock: self.config_manager.cached_fixed_input().clone(),
                 config_manager: self.config_manager.clone(),
+                lock_reviewed: self.lock_reviewed,
+                warnings: Vec::new(),
                 kind: OngoingSaveProgressKind::SaveToConfigFile,
             },
             |mut state| async move {
                 match state.kind {
                     OngoingSaveProgressKind::SaveToConfigFile => {
-                        state.config_manager.save_to_config_file().await;
+                        if let Err(err) = state.config_manager.save_to_config_file().await {
+                            state.kind = OngoingSaveProgressKind::Final;
+                            return Some((
+                                Some(OngoingSaveProgressMessage::Failed(err.to_string())),
+                                state,
+                            ));
+                        };
                         state.kind = OngoingSaveProgressKind::GenerateInputsSet;
                         Some((
                             Some(OngoingSaveProgressMessage::Done(
@@ -76,8 +113,21 @@ impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
                     }
                     OngoingSaveProgressKind::GenerateInputsSet => {
                         let inputs_set =
-                            state.config_manager.generate_inputs_set_for_enabled().await;
-                        state.kind = OngoingSaveProgressKind::EnsureFixedLoaded(inputs_set, 0);
+                            match state.config_manager.generate_inputs_set_for_enabled() {
+                                Ok(inputs_set) => inputs_set,
+                                Err(err) => {
+                                    state.kind = OngoingSaveProgressKind::Final;
+                                    return Some((
+                                        Some(OngoingSaveProgressMessage::Failed(err.to_string())),
+                                        state,
+                                    ));
+                                }
+                            };
+                        state.kind = if state.lock_reviewed {
+                            OngoingSaveProgressKind::SavePackageFile(inputs_set)
+                        } else {
+                            OngoingSaveProgressKind::EnsureFixedLoaded(inputs_set, 0)
+                        };
                         Some((
                             Some(OngoingSaveProgressMessage::Done(
                                 "inputs set generated".to_string(),
@@ -90,25 +140,61 @@ impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
                         position,
                     ) => {
                         if inputs_set.dependancies.len() <= position {
+                            let pruned = state.config_manager.prune_lock(&inputs_set);
+                            let diff = LockDiff::new(
+                                &state.previous_lock,
+                                state.config_manager.cached_fixed_input(),
+                            );
+                            if !diff.is_empty() {
+                                let review = LockUpdateReview {
+                                    diff,
+                                    updated: state.config_manager.cached_fixed_input().clone(),
+                                    pruned,
+                                };
+                                state.kind = OngoingSaveProgressKind::Final;
+                                return Some((
+                                    Some(OngoingSaveProgressMessage::ReviewLockUpdate(review)),
+                                    state,
+                                ));
+                            };
                             state.kind = OngoingSaveProgressKind::SavePackageFile((
                                 inputs_set,
                                 link_to_name,
                             ));
                             return Some((
                                 Some(OngoingSaveProgressMessage::Done(
-                                    "finished loading fixed input".to_string(),
+                                    format!("finished loading fixed input\n{}", pruned.describe())
+                                        .trim_end()
+                                        .to_string(),
                                 )),
                                 state,
                             ));
                         };
-                        state
+                        let distant = &inputs_set.dependancies[position].distant;
+                        let previous_state = match state
                             .config_manager
-                            .ensure_fixed_is_loaded(&inputs_set.dependancies[position].distant)
-                            .await;
-                        let status = format!(
-                            "finished to load fixed input from {:?}",
-                            &inputs_set.dependancies[position].distant
-                        );
+                            .ensure_fixed_is_loaded(distant, inputs_set.update_policy(distant))
+                            .await
+                        {
+                            Ok(previous_state) => previous_state,
+                            Err(err) => {
+                                state.kind = OngoingSaveProgressKind::Final;
+                                return Some((
+                                    Some(OngoingSaveProgressMessage::Failed(err.to_string())),
+                                    state,
+                                ));
+                            }
+                        };
+                        let status = match previous_state {
+                            Some(LockState::Stale) => format!(
+                                "{:?} changed since it was locked, locked it again",
+                                distant
+                            ),
+                            Some(LockState::Expired) => {
+                                format!("the lock of {:?} expired, updated it", distant)
+                            }
+                            _ => format!("finished to load fixed input from {:?}", distant),
+                        };
 
                         state.kind = OngoingSaveProgressKind::EnsureFixedLoaded(
                             (inputs_set, link_to_name),
@@ -117,10 +203,20 @@ impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
                         Some((Some(OngoingSaveProgressMessage::Done(status)), state))
                     }
                     OngoingSaveProgressKind::SavePackageFile((inputs_set, link_to_name)) => {
-                        let package_path = state
+                        let package_path = match state
                             .config_manager
-                            .write_nix_package_file(&inputs_set, &link_to_name)
-                            .await;
+                            .write_nix_package_file(&inputs_set, &link_to_name, &mut state.warnings)
+                            .await
+                        {
+                            Ok(package_path) => package_path,
+                            Err(err) => {
+                                state.kind = OngoingSaveProgressKind::Final;
+                                return Some((
+                                    Some(OngoingSaveProgressMessage::Failed(err.to_string())),
+                                    state,
+                                ));
+                            }
+                        };
                         state.kind = OngoingSaveProgressKind::TryFormatPackage(package_path, None);
                         Some((
                             Some(OngoingSaveProgressMessage::Done(
@@ -188,7 +284,12 @@ impl<H: Hasher, I> Recipe<H, I> for OngoingSave {
                     }
                     OngoingSaveProgressKind::Finished => {
                         state.kind = OngoingSaveProgressKind::Final;
-                        Some((None, state))
+                        let lock = state.config_manager.cached_fixed_input().clone();
+                        let warnings = std::mem::take(&mut state.warnings);
+                        Some((
+                            Some(OngoingSaveProgressMessage::Finished(lock, warnings)),
+                            state,
+                        ))
                     }
                     OngoingSaveProgressKind::Final => None,
                 }
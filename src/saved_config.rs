@@ -1,12 +1,30 @@
 use crate::config_manager::UserConfiguration;
+use crate::input_override::InputOverride;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum SaveConfigError {
+        CantWriteFile { path: PathBuf, err: io::Error } {
+            cause(err)
+            display(me) -> ("can't write the file {:?}: {}", path, err)
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize, Default)]
 pub struct SavedConfig {
     pub configurations: HashMap<String, (bool, UserConfiguration)>,
+    /// how many days the pins of inputs no longer used are kept in the lock file
+    #[serde(default = "u64::default")]
+    pub keep_unused_pins_days: u64,
+    /// the rules that replace an input by another in every config source
+    #[serde(default = "Vec::default")]
+    pub input_overrides: Vec<InputOverride>,
 }
 
 impl SavedConfig {
@@ -20,3 +38,74 @@ impl SavedConfig {
         }
     }
 }
+
+/// The value of the secret options, saved in a separate file only readable by its owner
+#[derive(Deserialize, Serialize, Default, Clone, Hash)]
+pub struct SavedSecrets {
+    /// config key -> option id -> secret
+    pub secrets: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl SavedSecrets {
+    pub fn new_from_path(path: &Path) -> Self {
+        match File::open(path) {
+            Ok(file) => serde_json::from_reader(file).unwrap(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                eprintln!("impossible to load the secrets file: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// save the secrets, in a file only readable and writable by its owner
+    pub async fn save(&self, path: &Path) -> Result<(), SaveConfigError> {
+        use async_std::fs::OpenOptions;
+        use async_std::os::unix::fs::OpenOptionsExt;
+        use async_std::prelude::*;
+        use std::os::unix::fs::PermissionsExt;
+        let write = async {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(path)
+                .await?;
+            // the mode is only used when the file is created
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await?;
+            file.write_all(&serde_json::to_vec(self).unwrap()).await
+        };
+        write.await.map_err(|err| SaveConfigError::CantWriteFile {
+            path: path.to_path_buf(),
+            err,
+        })
+    }
+}
+
+#[test]
+fn test_save_secrets() {
+    use std::os::unix::fs::PermissionsExt;
+    let directory = crate::temp_dir::TempDir::new("secrets-test").unwrap();
+    let path = directory.path().join("config.secrets.json");
+    let mut saved_secrets = SavedSecrets::default();
+    saved_secrets
+        .secrets
+        .entry("source".to_string())
+        .or_default()
+        .insert("password".to_string(), "hunter2".to_string());
+    // the permissions are also fixed when the file already exist
+    std::fs::write(&path, "").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    async_std::task::block_on(saved_secrets.save(&path)).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let loaded = SavedSecrets::new_from_path(&path);
+    assert_eq!(loaded.secrets, saved_secrets.secrets);
+
+    assert!(matches!(
+        async_std::task::block_on(saved_secrets.save(&directory.path().join("missing/file"))),
+        Err(SaveConfigError::CantWriteFile { .. })
+    ));
+}
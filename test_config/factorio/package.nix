@@ -4,5 +4,5 @@ pkgs.factorio.override {
 	releaseType = user_config.releaseType;
 	username = user_config.username;
 	token = user_config.token;
-	experimental = user_config.stable == "false";
+	experimental = !user_config.stable;
 }